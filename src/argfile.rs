@@ -0,0 +1,63 @@
+// Support for `@path` arguments, which are replaced by the arguments listed in
+// the file at that path, one per line, like rustc's. Blank lines and lines
+// starting with `#` are skipped, and a file may itself contain `@path`
+// arguments. Paths are relative to the current directory. Arguments after
+// `--` are passed through untouched, since rustc expands them on its own.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+
+// Deep enough for any sensible use, shallow enough to stop a file that
+// includes itself.
+const MAX_DEPTH: usize = 16;
+
+pub fn expand(args: impl IntoIterator<Item = OsString>) -> io::Result<Vec<OsString>> {
+    let mut expanded = Vec::new();
+    let mut passthrough = false;
+    for arg in args {
+        push(arg, 0, &mut expanded, &mut passthrough)?;
+    }
+    Ok(expanded)
+}
+
+fn push(
+    arg: OsString,
+    depth: usize,
+    expanded: &mut Vec<OsString>,
+    passthrough: &mut bool,
+) -> io::Result<()> {
+    if *passthrough {
+        expanded.push(arg);
+        return Ok(());
+    }
+
+    let path = match arg.to_str() {
+        Some(arg) if arg.len() > 1 && arg.starts_with('@') => arg[1..].to_owned(),
+        _ => {
+            *passthrough = arg == "--";
+            expanded.push(arg);
+            return Ok(());
+        }
+    };
+
+    if depth == MAX_DEPTH {
+        let message = format!(
+            "response files nested more than {} deep at @{}",
+            MAX_DEPTH, path,
+        );
+        return Err(io::Error::new(io::ErrorKind::Other, message));
+    }
+
+    let content = fs::read_to_string(&path).map_err(|err| {
+        let message = format!("failed to read response file {}: {}", path, err);
+        io::Error::new(err.kind(), message)
+    })?;
+    for line in content.lines() {
+        let line = line.trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            push(line.into(), depth + 1, expanded, passthrough)?;
+        }
+    }
+    Ok(())
+}
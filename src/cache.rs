@@ -0,0 +1,228 @@
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::SystemTime;
+
+use crate::config;
+use crate::error::Result;
+use crate::fmt::Formatter;
+use crate::metadata::LazyMetadata;
+use crate::opts::{Args, Coloring};
+
+// A single cached expansion per package, stored under the target directory.
+// The first line of the file is the key of the inputs that produced it and
+// the rest is the formatted content.
+pub struct Cache {
+    path: PathBuf,
+    key: String,
+}
+
+impl Cache {
+    pub fn new(
+        args: &Args,
+        formatter: Option<&Formatter>,
+        metadata: &mut LazyMetadata,
+    ) -> Result<Self> {
+        let mut hasher = DefaultHasher::new();
+        env!("CARGO_PKG_VERSION").hash(&mut hasher);
+        hash_options(args, formatter, &mut hasher);
+        rustc_version()?.hash(&mut hasher);
+        rustflags().hash(&mut hasher);
+
+        let package = metadata.package()?;
+        let filename = format!("{}.cache", package.name);
+        let package_dir = package.manifest_path.parent().map(Path::to_owned);
+        let workspace = metadata.get()?;
+        let target_directory = &workspace.target_directory;
+
+        // Dependencies other than workspace members are pinned by Cargo.lock,
+        // and the root manifest holds [patch] and profiles even when it is a
+        // virtual one.
+        let mut sources = Vec::new();
+        for package in &workspace.packages {
+            if !workspace.workspace_members.contains(&package.id) {
+                continue;
+            }
+            if let Some(dir) = package.manifest_path.parent() {
+                collect_sources(dir, target_directory, &mut sources)?;
+            }
+        }
+        for name in &["Cargo.toml", "Cargo.lock"] {
+            let path = workspace.workspace_root.join(name);
+            if let Ok(metadata) = fs::metadata(&path) {
+                sources.push((path, metadata.len(), metadata.modified()?));
+            }
+        }
+        sources.sort();
+        sources.dedup();
+        sources.hash(&mut hasher);
+
+        // Cargo config may set rustflags and cfgs, and is looked up from the
+        // directory cargo runs in as well as the package's.
+        let mut config_files = config::cargo_config_files(&env::current_dir()?);
+        if let Some(package_dir) = &package_dir {
+            config_files.extend(config::cargo_config_files(package_dir));
+        }
+        config_files.sort();
+        config_files.dedup();
+        for path in config_files {
+            path.hash(&mut hasher);
+            fs::read(&path)?.hash(&mut hasher);
+        }
+
+        Ok(Cache {
+            path: target_directory.join("expand").join(filename),
+            key: format!("{:016x}", hasher.finish()),
+        })
+    }
+
+    // The cached content, if it was produced from the same inputs.
+    pub fn load(&self) -> Option<String> {
+        let content = fs::read_to_string(&self.path).ok()?;
+        let mut parts = content.splitn(2, '\n');
+        if parts.next()? == self.key {
+            parts.next().map(str::to_owned)
+        } else {
+            None
+        }
+    }
+
+    pub fn store(&self, content: &str) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&self.path, format!("{}\n{}", self.key, content))
+    }
+}
+
+// The options that change the content: the command line that runs the
+// expansion, the formatter and the edits to the syntax tree. Options that only
+// change how the content is shown are left out, and so is the selection of
+// items, which is applied to the cached content.
+fn hash_options(args: &Args, formatter: Option<&Formatter>, hasher: &mut DefaultHasher) {
+    let mut args = args.clone();
+    args.verbose = 0;
+    args.quiet = false;
+    let line = crate::expansion_line(&args, &Coloring::Never, Path::new("expanded"));
+    line.into_iter().collect::<Vec<OsString>>().hash(hasher);
+
+    format!("{:?}", formatter).hash(hasher);
+    let edits = (
+        args.compact,
+        args.flatten,
+        args.sorted,
+        args.no_sanitize,
+        args.keep_docs,
+        args.tidy_imports,
+        args.inline_includes,
+        args.inline_limit,
+        &args.module,
+        &args.strip_attrs,
+        &args.exclude_item,
+        &args.only,
+    );
+    format!("{:?}", edits).hash(hasher);
+    args.normalize.hash(hasher);
+}
+
+// RUSTFLAGS and its variants, such as CARGO_ENCODED_RUSTFLAGS and
+// CARGO_TARGET_<triple>_RUSTFLAGS, sorted by name.
+fn rustflags() -> Vec<(OsString, OsString)> {
+    let mut rustflags: Vec<(OsString, OsString)> = env::vars_os()
+        .filter(|(name, _)| {
+            let name = name.to_string_lossy();
+            name == "RUSTFLAGS" || (name.starts_with("CARGO_") && name.ends_with("RUSTFLAGS"))
+        })
+        .collect();
+    rustflags.sort();
+    rustflags
+}
+
+fn rustc_version() -> Result<Vec<u8>> {
+    let output = Command::new(crate::rustc_binary()).arg("-vV").output()?;
+    Ok(output.stdout)
+}
+
+// Path, size and modification time of every file in the package directory,
+// skipping the target directory and hidden directories such as .git.
+fn collect_sources(
+    dir: &Path,
+    target_directory: &Path,
+    sources: &mut Vec<(PathBuf, u64, SystemTime)>,
+) -> io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let path = entry.path();
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if !hidden && path != target_directory {
+                collect_sources(&path, target_directory, sources)?;
+            }
+        } else {
+            sources.push((path, metadata.len(), metadata.modified()?));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use structopt::StructOpt;
+
+    fn key(argv: &[&str]) -> u64 {
+        let args = Args::from_iter(argv);
+        let mut hasher = DefaultHasher::new();
+        hash_options(&args, None, &mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn display_options_keep_the_key() {
+        let plain = key(&["expand", "--lib"]);
+        let displayed = key(&[
+            "expand",
+            "--lib",
+            "--color=always",
+            "--theme=GitHub",
+            "--pager=never",
+            "--line-numbers",
+            "--timings",
+            "--output=expanded.rs",
+            "foo",
+            "-v",
+        ]);
+        assert_eq!(plain, displayed);
+        assert_eq!(plain, key(&["expand", "--lib", "--quiet"]));
+    }
+
+    #[test]
+    fn expansion_options_change_the_key() {
+        let plain = key(&["expand", "--lib"]);
+        assert_ne!(plain, key(&["expand", "--lib", "--features=foo"]));
+        assert_ne!(plain, key(&["expand", "--lib", "--release"]));
+        assert_ne!(plain, key(&["expand", "--lib", "--sorted"]));
+        assert_ne!(plain, key(&["expand", "--bin=foo"]));
+    }
+
+    #[test]
+    fn load_checks_the_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("expand").join("foo.cache");
+        let cache = |key: &str| Cache {
+            path: path.clone(),
+            key: key.to_owned(),
+        };
+
+        assert_eq!(cache("a").load(), None);
+        cache("a").store("mod m {}\n").unwrap();
+        assert_eq!(cache("a").load().as_deref(), Some("mod m {}\n"));
+        assert_eq!(cache("b").load(), None);
+    }
+}
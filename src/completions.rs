@@ -0,0 +1,98 @@
+// Shell completion scripts for --completions. Clap generates them from the
+// options in opts.rs, and only knows to complete the values of options as file
+// paths. Where the shell makes it possible, options naming a target or a theme
+// are changed to ask cargo expand for the names at the time of completion.
+
+use structopt::clap::Shell;
+use structopt::StructOpt;
+
+use crate::opts::Opts;
+
+// Options whose values come from running a command, and whether paths are
+// also valid values.
+const DYNAMIC: &[(&str, &str, bool)] = &[
+    ("bin", "cargo expand --complete-targets bin", false),
+    ("example", "cargo expand --complete-targets example", false),
+    ("test", "cargo expand --complete-targets test", false),
+    ("bench", "cargo expand --complete-targets bench", false),
+    ("theme", "cargo expand --themes", true),
+];
+
+pub fn generate(shell: Shell) -> String {
+    let mut script = Vec::new();
+    Opts::clap().gen_completions_to("cargo", shell, &mut script);
+    let mut script = String::from_utf8_lossy(&script).into_owned();
+
+    for &(option, command, paths) in DYNAMIC {
+        script = match shell {
+            Shell::Bash => bash(&script, option, command, paths),
+            Shell::Zsh => zsh(&script, option, command, paths),
+            Shell::Fish => fish(&script, option, command, paths),
+            Shell::PowerShell | Shell::Elvish => script,
+        };
+    }
+    script
+}
+
+fn bash(script: &str, option: &str, command: &str, paths: bool) -> String {
+    let case = format!("                --{})\n", option);
+    let generated = format!(
+        "{}                    COMPREPLY=($(compgen -f \"${{cur}}\"))\n",
+        case
+    );
+    // Theme names have spaces in them.
+    let mut reply = format!(
+        "local IFS=$'\\n'\n                    \
+         COMPREPLY=($(compgen -W \"$({} 2>/dev/null)\" -- \"${{cur}}\" | sed 's/ /\\\\ /g')",
+        command,
+    );
+    if paths {
+        reply += " $(compgen -f \"${cur}\")";
+    }
+    reply += ")\n";
+    script.replace(
+        &generated,
+        &format!("{}                    {}", case, reply),
+    )
+}
+
+fn zsh(script: &str, option: &str, command: &str, paths: bool) -> String {
+    let start = format!("'--{}=[", option);
+    let mut action = format!(
+        ":{}:{{compadd -- ${{(f)\"$({} 2>/dev/null)\"}}",
+        option.to_uppercase(),
+        command,
+    );
+    if paths {
+        action += "; _files";
+    }
+    action += "}";
+    let mut lines = Vec::new();
+    for line in script.lines() {
+        if line.starts_with(&start) && line.ends_with("]' \\") {
+            let line = &line[..line.len() - "' \\".len()];
+            lines.push(format!("{}{}' \\", line, action));
+        } else {
+            lines.push(line.to_owned());
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+fn fish(script: &str, option: &str, command: &str, paths: bool) -> String {
+    let needle = format!(" -l {} -d ", option);
+    let mut lines = Vec::new();
+    for line in script.lines() {
+        if line.contains(&needle) {
+            // Fish completes paths too unless told not to with -f.
+            let files = if paths { "" } else { " -f" };
+            lines.push(format!(
+                "{} -r{} -a \"({} 2>/dev/null)\"",
+                line, files, command
+            ));
+        } else {
+            lines.push(line.to_owned());
+        }
+    }
+    lines.join("\n") + "\n"
+}
@@ -1,9 +1,14 @@
+use serde::de::DeserializeOwned;
 use serde::Deserialize;
 
 use std::env;
 use std::fs;
 use std::io::{self, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use crate::opts::Args;
+
+const PROJECT_CONFIG: &str = ".cargo-expand.toml";
 
 #[derive(Deserialize)]
 struct Sections {
@@ -15,12 +20,45 @@ struct Sections {
 pub struct Config {
     pub theme: Option<String>,
     pub color: Option<String>,
-    #[serde(default)]
-    pub pager: bool,
+    pub pager: Option<bool>,
+    #[serde(rename = "line-numbers")]
+    pub line_numbers: Option<bool>,
+    #[serde(rename = "tab-width")]
+    pub tab_width: Option<usize>,
+    pub edition: Option<String>,
+    #[serde(rename = "max-width")]
+    pub max_width: Option<usize>,
+    pub features: Option<String>,
 }
 
-pub fn deserialize() -> Config {
-    try_deserialize().unwrap_or_default()
+impl Config {
+    // Settings from self, falling back to the other config for any that are
+    // not set.
+    fn or(self, fallback: Config) -> Config {
+        Config {
+            theme: self.theme.or(fallback.theme),
+            color: self.color.or(fallback.color),
+            pager: self.pager.or(fallback.pager),
+            line_numbers: self.line_numbers.or(fallback.line_numbers),
+            tab_width: self.tab_width.or(fallback.tab_width),
+            edition: self.edition.or(fallback.edition),
+            max_width: self.max_width.or(fallback.max_width),
+            features: self.features.or(fallback.features),
+        }
+    }
+}
+
+// The [expand] section of the global cargo config, overridden setting by
+// setting by the nearest .cargo-expand.toml of the project.
+pub fn deserialize(project_dir: Option<&Path>) -> Config {
+    let global = try_deserialize().unwrap_or_default();
+    let project = project_dir
+        .and_then(find_project_config)
+        .and_then(|path| read::<Config>(&path));
+    match project {
+        Some(project) => project.or(global),
+        None => global,
+    }
 }
 
 fn try_deserialize() -> Option<Config> {
@@ -31,10 +69,93 @@ fn try_deserialize() -> Option<Config> {
         .map(|name| cargo_home.join(name))
         .find(|path| path.exists())?;
 
-    let content = fs::read(&config_path).ok()?;
+    let full_config: Sections = read(&config_path)?;
+    Some(full_config.expand)
+}
+
+// The target that cargo builds for when --target is not passed, if one is
+// configured by `build.target`, along with where it was configured.
+pub struct BuildTarget {
+    pub target: String,
+    pub source: String,
+}
+
+// Follows cargo's precedence: --config, then CARGO_BUILD_TARGET, then config
+// files from the current directory upward, then the one in CARGO_HOME.
+pub fn build_target(args: &Args) -> Option<BuildTarget> {
+    for config_override in args.config_overrides.iter().rev() {
+        let mut parts = config_override.splitn(2, '=');
+        if parts.next().map(str::trim) == Some("build.target") {
+            let value = parts.next()?.trim();
+            return Some(BuildTarget {
+                target: value.trim_matches('"').to_owned(),
+                source: format!("--config {}", config_override),
+            });
+        }
+    }
+
+    if let Some(target) = env::var_os("CARGO_BUILD_TARGET") {
+        return Some(BuildTarget {
+            target: target.to_string_lossy().into_owned(),
+            source: "CARGO_BUILD_TARGET".to_owned(),
+        });
+    }
+
+    for path in cargo_config_files(&env::current_dir().ok()?) {
+        if let Some(target) = read_build_target(&path) {
+            return Some(BuildTarget {
+                target,
+                source: format!("build.target in {}", path.display()),
+            });
+        }
+    }
+
+    None
+}
+
+// Cargo's own config files that apply to a build run from the given directory,
+// in order of precedence: .cargo/config.toml or .cargo/config in it and each of
+// its ancestors, then the one in CARGO_HOME.
+pub fn cargo_config_files(dir: &Path) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = dir.ancestors().map(|dir| dir.join(".cargo")).collect();
+    dirs.extend(env::var_os("CARGO_HOME").map(PathBuf::from));
+    let mut files = Vec::new();
+    for dir in dirs {
+        for name in &["config.toml", "config"] {
+            let path = dir.join(name);
+            if path.is_file() {
+                files.push(path);
+            }
+        }
+    }
+    files
+}
+
+fn read_build_target(path: &Path) -> Option<String> {
+    let content = fs::read(path).ok()?;
+    let config: toml::Value = toml::from_slice(&content).ok()?;
+    match config.get("build")?.get("target")? {
+        toml::Value::String(target) => Some(target.clone()),
+        // Cargo accepts a list of targets to build for all of them.
+        toml::Value::Array(targets) => {
+            let targets: Vec<&str> = targets.iter().filter_map(toml::Value::as_str).collect();
+            Some(targets.join(", "))
+        }
+        _ => None,
+    }
+}
+
+fn find_project_config(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .map(|dir| dir.join(PROJECT_CONFIG))
+        .find(|path| path.exists())
+}
 
-    let full_config: Sections = match toml::from_slice(&content) {
-        Ok(config) => config,
+fn read<T: DeserializeOwned>(config_path: &Path) -> Option<T> {
+    let content = fs::read(config_path).ok()?;
+
+    match toml::from_slice(&content) {
+        Ok(config) => Some(config),
         Err(err) => {
             let _ = writeln!(
                 &mut io::stderr(),
@@ -42,9 +163,7 @@ fn try_deserialize() -> Option<Config> {
                 config_path.display(),
                 err
             );
-            return None;
+            None
         }
-    };
-
-    Some(full_config.expand)
+    }
 }
@@ -0,0 +1,126 @@
+// Best-effort splicing of an expanded item back into the unexpanded source
+// file, so that it can be read in context. The alignment is textual: the lines
+// spanned by the matching items in the original source are replaced by the
+// expanded code, and everything else in the file is left as written.
+
+use std::fs;
+use std::path::Path;
+
+use quote::ToTokens;
+use syn::{File, Item};
+use syn_select::Selector;
+
+use crate::error::Result;
+use crate::metadata::{self, LazyMetadata};
+use crate::opts::Args;
+
+pub fn splice(
+    args: &Args,
+    metadata: &mut LazyMetadata,
+    selector: &Selector,
+    expanded: &str,
+) -> Result<Option<String>> {
+    let root = metadata::root_source(args, metadata)?;
+    let dir = root.parent().unwrap_or_else(|| Path::new("")).to_owned();
+    let path = selector.to_string();
+    let segments: Vec<&str> = path.split("::").collect();
+
+    let Located { source, ranges } = match locate(&root, &dir, &segments)? {
+        Some(found) => found,
+        None => return Ok(None),
+    };
+
+    let lines: Vec<&str> = source.lines().collect();
+    let first = ranges[0].0;
+    let indent: String = lines[first]
+        .chars()
+        .take_while(|ch| ch.is_whitespace())
+        .collect();
+
+    let mut content = String::new();
+    for (i, line) in lines.iter().enumerate() {
+        if i == first {
+            for expanded_line in expanded.trim_end().lines() {
+                if !expanded_line.is_empty() {
+                    content += &indent;
+                }
+                content += expanded_line;
+                content += "\n";
+            }
+        }
+        if ranges.iter().all(|(start, end)| i < *start || i > *end) {
+            content += line;
+            content += "\n";
+        }
+    }
+
+    Ok(Some(content))
+}
+
+struct Located {
+    source: String,
+    // 0-based inclusive line ranges.
+    ranges: Vec<(usize, usize)>,
+}
+
+// Find the file containing the items at the given path, following `mod m;`
+// declarations into their own files.
+fn locate(path: &Path, dir: &Path, segments: &[&str]) -> Result<Option<Located>> {
+    let source = fs::read_to_string(path)?;
+    let syntax_tree: File = match syn::parse_file(&source) {
+        Ok(syntax_tree) => syntax_tree,
+        Err(_) => return Ok(None),
+    };
+
+    if let Ok(selector) = Selector::try_from(segments.join("::")) {
+        let mut ranges: Vec<(usize, usize)> = selector
+            .apply_to(&syntax_tree)
+            .iter()
+            .filter_map(line_range)
+            .collect();
+        if !ranges.is_empty() {
+            ranges.sort();
+            return Ok(Some(Located { source, ranges }));
+        }
+    }
+
+    // Descend through inline modules to an out-of-line one.
+    let mut items = &syntax_tree.items;
+    let mut dir = dir.to_owned();
+    for (i, segment) in segments.iter().enumerate().take(segments.len() - 1) {
+        let module = items.iter().find_map(|item| match item {
+            Item::Mod(module) if module.ident == segment => Some(module),
+            _ => None,
+        });
+        let module = match module {
+            Some(module) => module,
+            None => return Ok(None),
+        };
+        dir.push(segment);
+        match &module.content {
+            Some((_, content)) => items = content,
+            None => {
+                let mut file = dir.with_extension("rs");
+                if !file.exists() {
+                    file = dir.join("mod.rs");
+                }
+                return locate(&file, &dir, &segments[i + 1..]);
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+fn line_range(item: &Item) -> Option<(usize, usize)> {
+    let mut tokens = item.to_token_stream().into_iter();
+    let first = tokens.next()?.span();
+    let last = tokens.last().map_or(first, |token| token.span());
+    let start = first.start();
+    let end = last.end();
+    if start.line == 0 {
+        // Span locations are not available.
+        return None;
+    }
+    Some((start.line - 1, end.line - 1))
+}
@@ -0,0 +1,40 @@
+use std::io::{self, Write};
+
+use similar::TextDiff;
+use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
+
+pub fn print(old_name: &str, old: &str, new_name: &str, new: &str, color: bool) -> io::Result<()> {
+    let color_choice = if color {
+        ColorChoice::Always
+    } else {
+        ColorChoice::Never
+    };
+
+    let diff = TextDiff::from_lines(old, new);
+    let unified = diff.unified_diff().header(old_name, new_name).to_string();
+
+    let mut stream = StandardStream::stdout(color_choice);
+    for line in unified.lines() {
+        let fg = if line.starts_with("+++") || line.starts_with("---") {
+            None
+        } else if line.starts_with('+') {
+            Some(Color::Green)
+        } else if line.starts_with('-') {
+            Some(Color::Red)
+        } else if line.starts_with("@@") {
+            Some(Color::Cyan)
+        } else {
+            None
+        };
+        if let Some(fg) = fg {
+            stream.set_color(ColorSpec::new().set_fg(Some(fg)))?;
+            write!(stream, "{}", line)?;
+            stream.reset()?;
+        } else {
+            write!(stream, "{}", line)?;
+        }
+        writeln!(stream)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,239 @@
+// Doctests are expanded as the examples of a synthetic package that depends on
+// the package being expanded, which is roughly how rustdoc compiles them. Only
+// fenced code blocks in `///` and `//!` comments of the crate root file are
+// found; `#[doc = "..."]` attributes and doc comments in other modules are not.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use toml::value::{Table, Value};
+
+use crate::error::Result;
+use crate::metadata::Package;
+use crate::opts::{Args, Edition};
+
+pub struct Doctest {
+    // 1-based line of the opening fence.
+    pub line: usize,
+    pub code: String,
+}
+
+// Code block attributes understood by rustdoc. A block whose info string has
+// anything else, like ```text, is not Rust.
+const RUST_ATTRS: &[&str] = &[
+    "rust",
+    "ignore",
+    "should_panic",
+    "no_run",
+    "compile_fail",
+    "test_harness",
+    "allow_fail",
+    "edition2015",
+    "edition2018",
+    "edition2021",
+    "edition2024",
+];
+
+pub fn extract(source: &str) -> Vec<Doctest> {
+    let mut doctests = Vec::new();
+    // Opening fence, its line, whether the block is worth expanding, and the
+    // lines so far.
+    let mut block: Option<(String, usize, bool, Vec<String>)> = None;
+    let mut prev_kind = None;
+
+    for (i, line) in source.lines().enumerate() {
+        let (kind, doc) = match doc_line(line) {
+            Some(doc_line) => doc_line,
+            None => {
+                // The doc comment ended, along with any unterminated block.
+                block = None;
+                prev_kind = None;
+                continue;
+            }
+        };
+        if prev_kind != Some(kind) {
+            block = None;
+        }
+        prev_kind = Some(kind);
+
+        let trimmed = doc.trim_start();
+        match &mut block {
+            None => {
+                if let Some(fence) = fence(trimmed) {
+                    let info = trimmed[fence.len()..].trim();
+                    block = Some((fence, i + 1, is_expandable(info), Vec::new()));
+                }
+            }
+            Some((fence, line, expandable, lines)) => {
+                if trimmed.starts_with(fence.as_str()) && trimmed[fence.len()..].trim().is_empty() {
+                    if *expandable {
+                        doctests.push(Doctest {
+                            line: *line,
+                            code: wrap(lines),
+                        });
+                    }
+                    block = None;
+                } else {
+                    lines.push(unhide(doc));
+                }
+            }
+        }
+    }
+
+    doctests
+}
+
+// The text of a `///` or `//!` comment line, and which of the two it is.
+fn doc_line(line: &str) -> Option<(char, &str)> {
+    let line = line.trim_start();
+    let (kind, rest) = if line.starts_with("///") && !line.starts_with("////") {
+        ('/', &line[3..])
+    } else if line.starts_with("//!") {
+        ('!', &line[3..])
+    } else {
+        return None;
+    };
+    let rest = if rest.starts_with(' ') {
+        &rest[1..]
+    } else {
+        rest
+    };
+    Some((kind, rest))
+}
+
+fn fence(line: &str) -> Option<String> {
+    for ch in &['`', '~'] {
+        let len = line.chars().take_while(|c| c == ch).count();
+        if len >= 3 {
+            return Some(ch.to_string().repeat(len));
+        }
+    }
+    None
+}
+
+// Rust code that is supposed to compile. Blocks marked compile_fail are left
+// out since they would only fail to expand.
+fn is_expandable(info: &str) -> bool {
+    let mut attrs = info
+        .split(|ch: char| ch == ',' || ch.is_whitespace())
+        .filter(|attr| !attr.is_empty());
+    attrs.all(|attr| RUST_ATTRS.contains(&attr) && attr != "compile_fail")
+}
+
+// Lines hidden from the rendered docs with a leading `#` are still part of the
+// doctest.
+fn unhide(line: &str) -> String {
+    let trimmed = line.trim_start();
+    if trimmed == "#" {
+        String::new()
+    } else if trimmed.starts_with("# ") {
+        trimmed[2..].to_owned()
+    } else if trimmed.starts_with("##") {
+        trimmed[1..].to_owned()
+    } else {
+        line.to_owned()
+    }
+}
+
+// Like rustdoc, wrap the code in a main function unless it has one, keeping
+// crate attributes at the top.
+fn wrap(lines: &[String]) -> String {
+    let mut code = String::from("#![allow(unused)]\n");
+    let mut body = String::new();
+    let mut prelude = true;
+    for line in lines {
+        if prelude && line.trim_start().starts_with("#![") {
+            code += line;
+            code.push('\n');
+        } else {
+            prelude = prelude && line.trim().is_empty();
+            body += line;
+            body.push('\n');
+        }
+    }
+    if body.contains("fn main") {
+        code += &body;
+    } else {
+        code += "fn main() {\n";
+        code += &body;
+        code += "}\n";
+    }
+    code
+}
+
+// Write the synthetic package into dir, with one example per doctest, and
+// return the path of its manifest and the names of the examples.
+pub fn write_package(
+    args: &Args,
+    package: &Package,
+    lib_name: &str,
+    edition: Edition,
+    doctests: &[Doctest],
+    dir: &Path,
+) -> Result<(PathBuf, Vec<String>)> {
+    let package_dir = package
+        .manifest_path
+        .parent()
+        .unwrap_or_else(|| Path::new("."));
+
+    let mut dependency = Table::new();
+    dependency.insert("package".to_owned(), Value::from(package.name.clone()));
+    dependency.insert(
+        "path".to_owned(),
+        Value::from(package_dir.to_string_lossy().into_owned()),
+    );
+    // The features of the package under expansion are selected through the
+    // dependency, since the synthetic package has none of its own.
+    let features: Vec<Value> = if args.all_features {
+        package.features.keys().cloned().map(Value::from).collect()
+    } else {
+        args.features
+            .iter()
+            .flat_map(|features| features.split(|ch: char| ch == ',' || ch.is_whitespace()))
+            .filter(|feature| !feature.is_empty())
+            .map(Value::from)
+            .collect()
+    };
+    if !features.is_empty() {
+        dependency.insert("features".to_owned(), Value::Array(features));
+    }
+    if args.no_default_features {
+        dependency.insert("default-features".to_owned(), Value::from(false));
+    }
+
+    let mut metadata = Table::new();
+    metadata.insert(
+        "name".to_owned(),
+        Value::from(format!("{}-doctests", package.name)),
+    );
+    metadata.insert("version".to_owned(), Value::from("0.0.0"));
+    metadata.insert("edition".to_owned(), Value::from(edition.to_string()));
+    metadata.insert("publish".to_owned(), Value::from(false));
+
+    let mut dependencies = Table::new();
+    dependencies.insert(lib_name.to_owned(), Value::Table(dependency));
+
+    let mut manifest = Table::new();
+    manifest.insert("package".to_owned(), Value::Table(metadata));
+    manifest.insert("dependencies".to_owned(), Value::Table(dependencies));
+    // Keep it out of any workspace that the directory happens to be in.
+    manifest.insert("workspace".to_owned(), Value::Table(Table::new()));
+
+    let examples_dir = dir.join("examples");
+    if examples_dir.exists() {
+        // Doctests from a previous run may be gone or at other lines.
+        fs::remove_dir_all(&examples_dir)?;
+    }
+    fs::create_dir_all(&examples_dir)?;
+
+    let mut names = Vec::new();
+    for doctest in doctests {
+        let name = format!("doctest_line_{}", doctest.line);
+        fs::write(examples_dir.join(&name).with_extension("rs"), &doctest.code)?;
+        names.push(name);
+    }
+
+    let manifest_path = dir.join("Cargo.toml");
+    fs::write(&manifest_path, toml::to_string(&manifest)?)?;
+    Ok((manifest_path, names))
+}
@@ -1,21 +1,440 @@
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use quote::ToTokens;
+use regex::Regex;
 use syn::visit_mut::{self, VisitMut};
 use syn::{
-    Attribute, Block, Expr, ExprArray, ExprAssign, ExprAssignOp, ExprAsync, ExprAwait, ExprBinary,
-    ExprBlock, ExprBox, ExprBreak, ExprCall, ExprCast, ExprClosure, ExprContinue, ExprField,
-    ExprForLoop, ExprGroup, ExprIf, ExprIndex, ExprLet, ExprLit, ExprLoop, ExprMacro, ExprMatch,
-    ExprMethodCall, ExprParen, ExprPath, ExprRange, ExprReference, ExprRepeat, ExprReturn,
-    ExprStruct, ExprTry, ExprTryBlock, ExprTuple, ExprType, ExprUnary, ExprUnsafe, ExprWhile,
-    ExprYield, File, Item, ItemMod, Stmt,
+    AttrStyle, Attribute, Block, Expr, ExprArray, ExprAssign, ExprAssignOp, ExprAsync, ExprAwait,
+    ExprBinary, ExprBlock, ExprBox, ExprBreak, ExprCall, ExprCast, ExprClosure, ExprContinue,
+    ExprField, ExprForLoop, ExprGroup, ExprIf, ExprIndex, ExprLet, ExprLit, ExprLoop, ExprMacro,
+    ExprMatch, ExprMethodCall, ExprParen, ExprPath, ExprRange, ExprReference, ExprRepeat,
+    ExprReturn, ExprStruct, ExprTry, ExprTryBlock, ExprTuple, ExprType, ExprUnary, ExprUnsafe,
+    ExprWhile, ExprYield, Field, File, Ident, ImplItem, Item, ItemMod, Lit, LitByteStr, LitStr,
+    Stmt, TraitItem, Type, Variant,
 };
+use syn_select::Selector;
+
+use crate::opts::ItemKind;
+
+pub enum Filter {
+    Path(Selector),
+    Regex(Regex),
+}
+
+impl Filter {
+    pub fn apply_to(&self, syntax_tree: &File) -> Vec<Item> {
+        match self {
+            Filter::Path(selector) => {
+                let mut items = selector.apply_to(syntax_tree);
+                let path = selector.to_string();
+                let segments: Vec<&str> = path.split("::").collect();
+                associated_items(&syntax_tree.items, &segments, &mut items);
+                items
+            }
+            Filter::Regex(regex) => syntax_tree
+                .items
+                .iter()
+                .filter(|item| item_name(item).map_or(false, |name| regex.is_match(&name)))
+                .cloned()
+                .collect(),
+        }
+    }
+}
+
+impl Display for Filter {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Filter::Path(selector) => selector.fmt(formatter),
+            Filter::Regex(regex) => {
+                // Undo the anchoring added by opts::parse_regex.
+                let regex = regex.as_str();
+                formatter.write_str(&regex[4..regex.len() - 2])
+            }
+        }
+    }
+}
+
+// For a path like `m::Foo::bar`, the impl blocks for `Foo` in module `m` cut
+// down to their associated items named `bar`. The impl keeps its generics,
+// trait and attributes, so that what is shown is still a valid impl. As in the
+// selector itself, `_` matches any name.
+fn associated_items(items: &[Item], segments: &[&str], found: &mut Vec<Item>) {
+    let matches = |segment: &str, ident: &Ident| segment == "_" || ident == segment;
+    match segments {
+        [] | [_] => {}
+        [self_ty, name] => {
+            for item in items {
+                let item = match item {
+                    Item::Impl(item) => item,
+                    _ => continue,
+                };
+                let ident = match &*item.self_ty {
+                    Type::Path(ty) => ty.path.segments.last().map(|segment| &segment.ident),
+                    _ => None,
+                };
+                if !ident.map_or(false, |ident| matches(self_ty, ident)) {
+                    continue;
+                }
+                let associated: Vec<ImplItem> = item
+                    .items
+                    .iter()
+                    .filter(|associated| {
+                        impl_item_ident(associated).map_or(false, |ident| matches(name, ident))
+                    })
+                    .cloned()
+                    .collect();
+                if !associated.is_empty() {
+                    let mut item = item.clone();
+                    item.items = associated;
+                    found.push(Item::Impl(item));
+                }
+            }
+        }
+        [module, rest @ ..] => {
+            for item in items {
+                if let Item::Mod(ItemMod {
+                    ident,
+                    content: Some((_, items)),
+                    ..
+                }) = item
+                {
+                    if matches(module, ident) {
+                        associated_items(items, rest, found);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn impl_item_ident(item: &ImplItem) -> Option<&Ident> {
+    match item {
+        ImplItem::Const(item) => Some(&item.ident),
+        ImplItem::Method(item) => Some(&item.sig.ident),
+        ImplItem::Type(item) => Some(&item.ident),
+        _ => None,
+    }
+}
+
+// Items matched by any of the filters, in source order, along with the filters
+// that matched nothing.
+pub fn select<'a>(filters: &'a [Filter], syntax_tree: &File) -> (Vec<Item>, Vec<&'a Filter>) {
+    let mut order = Vec::new();
+    source_order(&syntax_tree.items, &mut order);
+
+    let mut selected = Vec::new();
+    let mut unmatched = Vec::new();
+    for filter in filters {
+        let items = filter.apply_to(syntax_tree);
+        if items.is_empty() {
+            unmatched.push(filter);
+        }
+        for item in items {
+            let tokens = item.to_token_stream().to_string();
+            if selected.iter().all(|(_, _, existing)| *existing != tokens) {
+                let key = order_key(&item);
+                let position = order.iter().position(|t| *t == key);
+                selected.push((position.unwrap_or(usize::MAX), item, tokens));
+            }
+        }
+    }
+
+    // Stable, so items not found in the traversal keep the order of filters.
+    selected.sort_by_key(|(position, _, _)| *position);
+    let items = selected.into_iter().map(|(_, item, _)| item).collect();
+    (items, unmatched)
+}
+
+// The contents of the inline module at the given path, e.g. "a::b", or None
+// if there is no such module. With flatten, the contents of modules nested
+// inside it are hoisted up in place of the modules themselves.
+pub fn module_contents(path: &str, syntax_tree: &File, flatten: bool) -> Option<Vec<Item>> {
+    let mut items = &syntax_tree.items;
+    for segment in path.split("::") {
+        items = items.iter().find_map(|item| match item {
+            Item::Mod(ItemMod {
+                ident,
+                content: Some((_, items)),
+                ..
+            }) if ident == segment => Some(items),
+            _ => None,
+        })?;
+    }
+
+    let mut contents = Vec::new();
+    if flatten {
+        flatten_into(items, &mut contents);
+    } else {
+        contents = items.clone();
+    }
+    Some(contents)
+}
+
+fn flatten_into(items: &[Item], contents: &mut Vec<Item>) {
+    for item in items {
+        match item {
+            Item::Mod(ItemMod {
+                content: Some((_, items)),
+                ..
+            }) => flatten_into(items, contents),
+            item => contents.push(item.clone()),
+        }
+    }
+}
+
+// Move the contents of every inline module into a file of its own, laid out
+// the way rustc looks for them: `mod m;` in "lib.rs" refers to "m.rs", and
+// `mod n;` in "m.rs" to "m/n.rs". Returns each file's path relative to the
+// root file's directory, starting with the root file itself.
+pub fn split_modules(syntax_tree: File, root: &str) -> Vec<(PathBuf, File)> {
+    let mut files = Vec::new();
+    split_into(syntax_tree, PathBuf::new(), PathBuf::from(root), &mut files);
+    files
+}
 
-pub fn sanitize(syntax_tree: &mut File) {
+fn split_into(mut file: File, dir: PathBuf, path: PathBuf, files: &mut Vec<(PathBuf, File)>) {
+    let mut children = Vec::new();
+    for item in &mut file.items {
+        if let Item::Mod(module) = item {
+            if let Some((_, items)) = module.content.take() {
+                module.semi = Some(Default::default());
+                let (inner, outer) = module.attrs.drain(..).partition(|attr| match attr.style {
+                    AttrStyle::Inner(_) => true,
+                    AttrStyle::Outer => false,
+                });
+                module.attrs = outer;
+                let child = File {
+                    shebang: None,
+                    attrs: inner,
+                    items,
+                };
+                let name = module.ident.to_string();
+                children.push((child, dir.join(&name), dir.join(name + ".rs")));
+            }
+        }
+    }
+    files.push((path, file));
+    for (child, dir, path) in children {
+        split_into(child, dir, path, files);
+    }
+}
+
+// Items to remove from the output, identified by their tokens in the full
+// expansion so that they are still found after filtering moves them around.
+pub struct Exclude {
+    tokens: Vec<String>,
+}
+
+impl Exclude {
+    pub fn new(selectors: &[Selector], syntax_tree: &File) -> Self {
+        let tokens = selectors
+            .iter()
+            .flat_map(|selector| selector.apply_to(syntax_tree))
+            .map(|item| item.to_token_stream().to_string())
+            .collect();
+        Exclude { tokens }
+    }
+
+    pub fn apply_to(&self, syntax_tree: &mut File) {
+        if !self.tokens.is_empty() {
+            self.remove_from_vec_item(&mut syntax_tree.items);
+        }
+    }
+
+    fn remove_from_vec_item(&self, items: &mut Vec<Item>) {
+        items.retain(|item| !self.tokens.contains(&item.to_token_stream().to_string()));
+        for item in items {
+            if let Item::Mod(ItemMod {
+                content: Some((_, items)),
+                ..
+            }) = item
+            {
+                self.remove_from_vec_item(items);
+            }
+        }
+    }
+}
+
+// Keep only items of the given kinds. Modules of other kinds are kept if they
+// contain such items, so the module structure around them is preserved.
+pub fn retain_kinds(syntax_tree: &mut File, kinds: &[ItemKind]) {
+    if !kinds.is_empty() {
+        retain_kinds_in_vec_item(&mut syntax_tree.items, kinds);
+    }
+}
+
+fn retain_kinds_in_vec_item(items: &mut Vec<Item>, kinds: &[ItemKind]) {
+    let wanted = |item: &Item| item_kind(item).map_or(false, |kind| kinds.contains(&kind));
+    for item in items.iter_mut() {
+        if wanted(item) {
+            continue;
+        }
+        if let Item::Mod(ItemMod {
+            content: Some((_, items)),
+            ..
+        }) = item
+        {
+            retain_kinds_in_vec_item(items, kinds);
+        }
+    }
+    items.retain(|item| match item {
+        _ if wanted(item) => true,
+        Item::Mod(ItemMod {
+            content: Some((_, items)),
+            ..
+        }) => !items.is_empty(),
+        _ => false,
+    });
+}
+
+fn item_kind(item: &Item) -> Option<ItemKind> {
+    let kind = match item {
+        Item::Const(_) => ItemKind::Const,
+        Item::Enum(_) => ItemKind::Enum,
+        Item::ExternCrate(_) => ItemKind::ExternCrate,
+        Item::Fn(_) => ItemKind::Fn,
+        Item::ForeignMod(_) => ItemKind::ForeignMod,
+        Item::Impl(_) => ItemKind::Impl,
+        Item::Macro(_) | Item::Macro2(_) => ItemKind::Macro,
+        Item::Mod(_) => ItemKind::Mod,
+        Item::Static(_) => ItemKind::Static,
+        Item::Struct(_) => ItemKind::Struct,
+        Item::Trait(_) | Item::TraitAlias(_) => ItemKind::Trait,
+        Item::Type(_) => ItemKind::Type,
+        Item::Union(_) => ItemKind::Union,
+        Item::Use(_) => ItemKind::Use,
+        _ => return None,
+    };
+    Some(kind)
+}
+
+// Keys of every item, including those nested in modules, in pre-order.
+fn source_order(items: &[Item], order: &mut Vec<String>) {
+    for item in items {
+        order.push(order_key(item));
+        if let Item::Mod(ItemMod {
+            content: Some((_, items)),
+            ..
+        }) = item
+        {
+            source_order(items, order);
+        }
+    }
+}
+
+// What identifies an item in the traversal. Impl blocks go by their header,
+// so that one cut down to some of its associated items takes the place of the
+// whole impl.
+fn order_key(item: &Item) -> String {
+    match item {
+        Item::Impl(item) => {
+            let mut header = item.clone();
+            header.items.clear();
+            header.to_token_stream().to_string()
+        }
+        item => item.to_token_stream().to_string(),
+    }
+}
+
+// Name of a top-level item for matching against, using the self type for
+// impl blocks.
+pub fn item_name(item: &Item) -> Option<String> {
+    let ident = match item {
+        Item::Const(item) => &item.ident,
+        Item::Enum(item) => &item.ident,
+        Item::ExternCrate(item) => &item.ident,
+        Item::Fn(item) => &item.sig.ident,
+        Item::Macro(item) => item.ident.as_ref()?,
+        Item::Macro2(item) => &item.ident,
+        Item::Mod(item) => &item.ident,
+        Item::Static(item) => &item.ident,
+        Item::Struct(item) => &item.ident,
+        Item::Trait(item) => &item.ident,
+        Item::TraitAlias(item) => &item.ident,
+        Item::Type(item) => &item.ident,
+        Item::Union(item) => &item.ident,
+        Item::Impl(item) => {
+            let self_ty = item.self_ty.to_token_stream().to_string();
+            return Some(self_ty.replace(' ', ""));
+        }
+        _ => return None,
+    };
+    Some(ident.to_string())
+}
+
+pub struct Stats {
+    // Number of items of each kind that occurs, including items nested in
+    // modules, in the order of the first occurrence.
+    pub kinds: Vec<(ItemKind, usize)>,
+    // Path of the item other than a module with the most lines once formatted
+    // by prettyplease, and that number of lines.
+    pub largest: Option<(String, usize)>,
+}
+
+pub fn stats(syntax_tree: &File) -> Stats {
+    let mut stats = Stats {
+        kinds: Vec::new(),
+        largest: None,
+    };
+    stats_in_vec_item(&syntax_tree.items, &mut Vec::new(), &mut stats);
+    stats
+}
+
+fn stats_in_vec_item(items: &[Item], module: &mut Vec<String>, stats: &mut Stats) {
+    for item in items {
+        if let Some(kind) = item_kind(item) {
+            match stats.kinds.iter_mut().find(|(k, _)| *k == kind) {
+                Some((_, count)) => *count += 1,
+                None => stats.kinds.push((kind, 1)),
+            }
+        }
+
+        if let Item::Mod(item) = item {
+            if let Some((_, content)) = &item.content {
+                module.push(item.ident.to_string());
+                stats_in_vec_item(content, module, stats);
+                module.pop();
+            }
+            continue;
+        }
+
+        let file = File {
+            shebang: None,
+            attrs: Vec::new(),
+            items: vec![item.clone()],
+        };
+        let lines = prettyplease::unparse(&file).lines().count();
+        if stats
+            .largest
+            .as_ref()
+            .map_or(true, |(_, most)| lines > *most)
+        {
+            let name = match item {
+                Item::Impl(_) => format!("<impl {}>", item_name(item).unwrap_or_default()),
+                _ => item_name(item).unwrap_or_else(|| "<item>".to_owned()),
+            };
+            let mut path = module.join("::");
+            if !path.is_empty() {
+                path += "::";
+            }
+            stats.largest = Some((path + &name, lines));
+        }
+    }
+}
+
+pub fn sanitize(syntax_tree: &mut File, keep_docs: bool) {
     remove_macro_rules_from_vec_item(&mut syntax_tree.items);
-    Sanitize.visit_file_mut(syntax_tree);
+    Sanitize { keep_docs }.visit_file_mut(syntax_tree);
 }
 
 // - Remove all macro_rules
-// - Remove doc attributes on statements (dtolnay/cargo-expand#71)
-struct Sanitize;
+// - Remove doc attributes on statements (dtolnay/cargo-expand#71), unless
+//   keep_docs is set
+struct Sanitize {
+    keep_docs: bool,
+}
 
 impl VisitMut for Sanitize {
     fn visit_item_mod_mut(&mut self, i: &mut ItemMod) {
@@ -34,6 +453,10 @@ impl VisitMut for Sanitize {
     }
 
     fn visit_stmt_mut(&mut self, i: &mut Stmt) {
+        if self.keep_docs {
+            visit_mut::visit_stmt_mut(self, i);
+            return;
+        }
         match i {
             Stmt::Local(local) => remove_doc_attributes(&mut local.attrs),
             Stmt::Expr(e) | Stmt::Semi(e, _) => {
@@ -47,6 +470,298 @@ impl VisitMut for Sanitize {
     }
 }
 
+// Replace include_str! and include_bytes! calls with a literal of the file's
+// contents, for files of at most `limit` bytes. Paths are relative to dir.
+// Returns the calls that were left alone, by path, with the reason.
+pub fn inline_includes(syntax_tree: &mut File, dir: &Path, limit: u64) -> Vec<(String, String)> {
+    let mut visitor = InlineIncludes {
+        dir,
+        limit,
+        skipped: Vec::new(),
+    };
+    visitor.visit_file_mut(syntax_tree);
+    visitor.skipped
+}
+
+struct InlineIncludes<'a> {
+    dir: &'a Path,
+    limit: u64,
+    skipped: Vec<(String, String)>,
+}
+
+impl InlineIncludes<'_> {
+    fn contents(&self, path: &LitStr) -> Result<Vec<u8>, String> {
+        let file = self.dir.join(path.value());
+        let len = fs::metadata(&file).map_err(|err| err.to_string())?.len();
+        if len > self.limit {
+            return Err(format!(
+                "{} bytes is over the --inline-limit of {}",
+                len, self.limit,
+            ));
+        }
+        fs::read(&file).map_err(|err| err.to_string())
+    }
+
+    fn literal(&self, name: &str, path: &LitStr) -> Result<Lit, String> {
+        let contents = self.contents(path)?;
+        if name == "include_bytes" {
+            return Ok(Lit::ByteStr(LitByteStr::new(&contents, path.span())));
+        }
+        match String::from_utf8(contents) {
+            Ok(contents) => Ok(Lit::Str(LitStr::new(&contents, path.span()))),
+            Err(_) => Err("the file is not valid UTF-8".to_owned()),
+        }
+    }
+}
+
+impl VisitMut for InlineIncludes<'_> {
+    fn visit_expr_mut(&mut self, e: &mut Expr) {
+        if let Expr::Macro(expr) = e {
+            let name = match expr.mac.path.segments.last() {
+                Some(segment) => segment.ident.to_string(),
+                None => String::new(),
+            };
+            if name == "include_str" || name == "include_bytes" {
+                if let Ok(path) = expr.mac.parse_body::<LitStr>() {
+                    match self.literal(&name, &path) {
+                        Ok(lit) => {
+                            *e = Expr::Lit(ExprLit {
+                                attrs: expr.attrs.clone(),
+                                lit,
+                            });
+                        }
+                        Err(reason) => self.skipped.push((path.value(), reason)),
+                    }
+                }
+                return;
+            }
+        }
+        visit_mut::visit_expr_mut(self, e);
+    }
+}
+
+// Remove repeated `use` and `extern crate` items within each module and block,
+// keeping the first occurrence.
+pub fn tidy_imports(syntax_tree: &mut File) {
+    dedup_imports_in_vec_item(&mut syntax_tree.items);
+    TidyImports.visit_file_mut(syntax_tree);
+}
+
+struct TidyImports;
+
+impl VisitMut for TidyImports {
+    fn visit_item_mod_mut(&mut self, i: &mut ItemMod) {
+        if let Some((_, items)) = &mut i.content {
+            dedup_imports_in_vec_item(items);
+        }
+        visit_mut::visit_item_mod_mut(self, i);
+    }
+
+    fn visit_block_mut(&mut self, i: &mut Block) {
+        let mut seen = Vec::new();
+        i.stmts.retain(|stmt| match stmt {
+            Stmt::Item(item) => is_first_import(item, &mut seen),
+            _ => true,
+        });
+        visit_mut::visit_block_mut(self, i);
+    }
+}
+
+fn dedup_imports_in_vec_item(items: &mut Vec<Item>) {
+    let mut seen = Vec::new();
+    items.retain(|item| is_first_import(item, &mut seen));
+}
+
+// False for an import identical to one seen before; true for anything else.
+fn is_first_import(item: &Item, seen: &mut Vec<String>) -> bool {
+    match item {
+        Item::Use(_) | Item::ExternCrate(_) => {
+            let tokens = item.to_token_stream().to_string();
+            if seen.contains(&tokens) {
+                false
+            } else {
+                seen.push(tokens);
+                true
+            }
+        }
+        _ => true,
+    }
+}
+
+// Order in which kinds of items appear in --sorted output.
+const KIND_ORDER: &[ItemKind] = &[
+    ItemKind::ExternCrate,
+    ItemKind::Use,
+    ItemKind::Mod,
+    ItemKind::Macro,
+    ItemKind::Const,
+    ItemKind::Static,
+    ItemKind::Type,
+    ItemKind::Struct,
+    ItemKind::Enum,
+    ItemKind::Union,
+    ItemKind::Trait,
+    ItemKind::Impl,
+    ItemKind::Fn,
+    ItemKind::ForeignMod,
+];
+
+// Stably sort top-level items by kind and then name, falling back to their
+// tokens so that e.g. several impls for one type have a fixed order. Items
+// nested inside modules keep their original order.
+pub fn sort_items(syntax_tree: &mut File) {
+    syntax_tree.items.sort_by_cached_key(|item| {
+        let rank = item_kind(item)
+            .and_then(|kind| KIND_ORDER.iter().position(|k| *k == kind))
+            .unwrap_or(KIND_ORDER.len());
+        let name = item_name(item).unwrap_or_default();
+        let tokens = item.to_token_stream().to_string();
+        (rank, name, tokens)
+    });
+}
+
+// Attributes removed by a bare --strip-attrs: markers, lint allows and doc
+// hiding that the compiler's built-in derives and macros put on the code they
+// generate.
+pub const DEFAULT_STRIP_ATTRS: &[&str] = &[
+    "automatically_derived",
+    "doc(hidden)",
+    "allow(unused)",
+    "allow(unused_qualifications)",
+    "allow(unused_extern_crates)",
+    "allow(non_upper_case_globals)",
+    "allow(clippy::*)",
+];
+
+// Remove attributes matching any of the given names, either by path alone
+// (`inline`) or by the whole attribute (`allow(dead_code)`), where `*` matches
+// anything. A lint attribute listing several lints is removed when each of
+// them matches on its own, so `allow(unused)` also removes `allow(unused,
+// clippy::all)` when `allow(clippy::*)` is given too.
+pub fn strip_attrs(syntax_tree: &mut File, names: &[String]) {
+    if !names.is_empty() {
+        StripAttrs { names }.visit_file_mut(syntax_tree);
+    }
+}
+
+struct StripAttrs<'a> {
+    names: &'a [String],
+}
+
+impl StripAttrs<'_> {
+    fn strip(&self, attrs: &mut Vec<Attribute>) {
+        attrs.retain(|attr| {
+            let path = attr.path.to_token_stream().to_string().replace(' ', "");
+            let tokens = attr.tokens.to_string().replace(' ', "");
+            let meta = path.clone() + &tokens;
+            !(self.matches(&path) || self.matches(&meta) || self.matches_lints(&path, &tokens))
+        });
+    }
+
+    fn matches(&self, meta: &str) -> bool {
+        self.names.iter().any(|name| wildcard_match(name, meta))
+    }
+
+    fn matches_lints(&self, path: &str, tokens: &str) -> bool {
+        if !["allow", "warn", "deny", "forbid", "expect"].contains(&path) {
+            return false;
+        }
+        if tokens.len() <= 2 || !tokens.starts_with('(') || !tokens.ends_with(')') {
+            return false;
+        }
+        tokens[1..tokens.len() - 1]
+            .split(',')
+            .filter(|lint| !lint.is_empty())
+            .all(|lint| self.matches(&format!("{}({})", path, lint)))
+    }
+}
+
+// Whether text matches the pattern, in which `*` stands for any run of
+// characters.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let mut pieces = pattern.split('*');
+    let first = pieces.next().unwrap_or_default();
+    if !text.starts_with(first) {
+        return false;
+    }
+    let mut rest = &text[first.len()..];
+    let mut pieces: Vec<&str> = pieces.collect();
+    let last = match pieces.pop() {
+        Some(last) => last,
+        None => return rest.is_empty(),
+    };
+    for piece in pieces {
+        match rest.find(piece) {
+            Some(index) => rest = &rest[index + piece.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+impl VisitMut for StripAttrs<'_> {
+    fn visit_item_mut(&mut self, i: &mut Item) {
+        if let Some(attrs) = item_attrs_mut(i) {
+            self.strip(attrs);
+        }
+        visit_mut::visit_item_mut(self, i);
+    }
+
+    fn visit_impl_item_mut(&mut self, i: &mut ImplItem) {
+        match i {
+            ImplItem::Const(item) => self.strip(&mut item.attrs),
+            ImplItem::Method(item) => self.strip(&mut item.attrs),
+            ImplItem::Type(item) => self.strip(&mut item.attrs),
+            ImplItem::Macro(item) => self.strip(&mut item.attrs),
+            _ => {}
+        }
+        visit_mut::visit_impl_item_mut(self, i);
+    }
+
+    fn visit_trait_item_mut(&mut self, i: &mut TraitItem) {
+        match i {
+            TraitItem::Const(item) => self.strip(&mut item.attrs),
+            TraitItem::Method(item) => self.strip(&mut item.attrs),
+            TraitItem::Type(item) => self.strip(&mut item.attrs),
+            TraitItem::Macro(item) => self.strip(&mut item.attrs),
+            _ => {}
+        }
+        visit_mut::visit_trait_item_mut(self, i);
+    }
+
+    fn visit_field_mut(&mut self, i: &mut Field) {
+        self.strip(&mut i.attrs);
+        visit_mut::visit_field_mut(self, i);
+    }
+
+    fn visit_variant_mut(&mut self, i: &mut Variant) {
+        self.strip(&mut i.attrs);
+        visit_mut::visit_variant_mut(self, i);
+    }
+}
+
+fn item_attrs_mut(item: &mut Item) -> Option<&mut Vec<Attribute>> {
+    match item {
+        Item::Const(item) => Some(&mut item.attrs),
+        Item::Enum(item) => Some(&mut item.attrs),
+        Item::ExternCrate(item) => Some(&mut item.attrs),
+        Item::Fn(item) => Some(&mut item.attrs),
+        Item::ForeignMod(item) => Some(&mut item.attrs),
+        Item::Impl(item) => Some(&mut item.attrs),
+        Item::Macro(item) => Some(&mut item.attrs),
+        Item::Macro2(item) => Some(&mut item.attrs),
+        Item::Mod(item) => Some(&mut item.attrs),
+        Item::Static(item) => Some(&mut item.attrs),
+        Item::Struct(item) => Some(&mut item.attrs),
+        Item::Trait(item) => Some(&mut item.attrs),
+        Item::TraitAlias(item) => Some(&mut item.attrs),
+        Item::Type(item) => Some(&mut item.attrs),
+        Item::Union(item) => Some(&mut item.attrs),
+        Item::Use(item) => Some(&mut item.attrs),
+        _ => None,
+    }
+}
+
 fn remove_macro_rules_from_vec_item(items: &mut Vec<Item>) {
     items.retain(|item| match item {
         Item::Macro(_) => false,
@@ -102,3 +817,285 @@ fn attrs_mut(e: &mut Expr) -> Option<&mut Vec<Attribute>> {
         _ => None,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tokens(source: &str) -> String {
+        let syntax_tree: File = syn::parse_file(source).unwrap();
+        syntax_tree.to_token_stream().to_string()
+    }
+
+    fn sanitized(source: &str, keep_docs: bool) -> String {
+        let mut syntax_tree = syn::parse_file(source).unwrap();
+        sanitize(&mut syntax_tree, keep_docs);
+        syntax_tree.to_token_stream().to_string()
+    }
+
+    #[test]
+    fn sanitize_removes_macro_rules() {
+        let source = r#"
+            macro_rules! outer {
+                () => { $crate::inner!() };
+            }
+            mod m {
+                macro_rules! nested {
+                    () => {};
+                }
+                pub fn f() {
+                    macro_rules! local {
+                        () => {};
+                    }
+                }
+            }
+        "#;
+        let expected = r#"
+            mod m {
+                pub fn f() {}
+            }
+        "#;
+        assert_eq!(sanitized(source, false), tokens(expected));
+    }
+
+    #[test]
+    fn sanitize_removes_docs_on_statements() {
+        // Comments that rustc attaches to the statement after them.
+        let source = r#"
+            /// Kept on items.
+            fn f() {
+                #[doc = " misplaced"]
+                let x = 1;
+                #[doc = " misplaced"]
+                x;
+            }
+        "#;
+        let expected = r#"
+            /// Kept on items.
+            fn f() {
+                let x = 1;
+                x;
+            }
+        "#;
+        assert_eq!(sanitized(source, false), tokens(expected));
+        assert_eq!(sanitized(source, true), tokens(source));
+    }
+
+    #[test]
+    fn sanitize_keeps_derived_impls() {
+        let source = r#"
+            struct S;
+            #[automatically_derived]
+            impl ::core::clone::Clone for S {
+                #[inline]
+                fn clone(&self) -> S {
+                    S
+                }
+            }
+        "#;
+        assert_eq!(sanitized(source, false), tokens(source));
+    }
+
+    #[test]
+    fn sanitize_is_idempotent() {
+        let source = r#"
+            macro_rules! m {
+                () => { $crate::f() };
+            }
+            pub fn f() {
+                #[doc = " misplaced"]
+                let _x = Ξcrate::g();
+                macro_rules! local {
+                    () => {};
+                }
+            }
+            mod inner {
+                macro_rules! nested {
+                    () => {};
+                }
+            }
+        "#;
+        let mut once = syn::parse_file(source).unwrap();
+        sanitize(&mut once, false);
+        let mut twice = once.clone();
+        sanitize(&mut twice, false);
+        assert_eq!(
+            twice.to_token_stream().to_string(),
+            once.to_token_stream().to_string(),
+        );
+    }
+
+    #[test]
+    fn select_items() {
+        let syntax_tree = syn::parse_file(
+            r#"
+                pub struct Foo;
+                pub struct MyFoo;
+                mod m {
+                    pub fn bar() {}
+                }
+            "#,
+        )
+        .unwrap();
+        let filters = vec![
+            Filter::Path("m::bar".parse().unwrap()),
+            Filter::Regex(Regex::new("^(?:Foo.*)$").unwrap()),
+            Filter::Path("missing".parse().unwrap()),
+        ];
+        let (items, unmatched) = select(&filters, &syntax_tree);
+        let names: Vec<String> = items.iter().filter_map(item_name).collect();
+        assert_eq!(names, ["Foo", "bar"]);
+        assert_eq!(unmatched.len(), 1);
+        assert_eq!(unmatched[0].to_string(), "missing");
+    }
+
+    #[test]
+    fn select_associated_items() {
+        let syntax_tree = syn::parse_file(
+            r#"
+                pub struct Foo<T>(T);
+                impl<T: Clone> Clone for Foo<T> {
+                    fn clone(&self) -> Self {
+                        Foo(self.0.clone())
+                    }
+                }
+                impl<T> Foo<T> {
+                    pub const N: usize = 1;
+                    pub fn bar(&self) {}
+                }
+            "#,
+        )
+        .unwrap();
+        let filters = vec![
+            Filter::Path("Foo::bar".parse().unwrap()),
+            Filter::Path("Foo::clone".parse().unwrap()),
+        ];
+        let (items, unmatched) = select(&filters, &syntax_tree);
+        assert!(unmatched.is_empty());
+        let expected = syn::parse_file(
+            r#"
+                impl<T: Clone> Clone for Foo<T> {
+                    fn clone(&self) -> Self {
+                        Foo(self.0.clone())
+                    }
+                }
+                impl<T> Foo<T> {
+                    pub fn bar(&self) {}
+                }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            quote::quote!(#(#items)*).to_string(),
+            expected.to_token_stream().to_string(),
+        );
+    }
+
+    #[test]
+    fn strip_default_attrs_from_derive() {
+        // #[derive(Debug, Eq)] as expanded by rustc 1.97 on a struct with a
+        // user-written allow, which the derive copies onto its impls, and
+        // #[derive(Debug)] as expanded by rustc 1.65.
+        let mut syntax_tree = syn::parse_file(
+            r#"
+                #[allow(dead_code)]
+                pub struct S {
+                    a: u8,
+                }
+                #[automatically_derived]
+                #[allow(dead_code)]
+                impl ::core::fmt::Debug for S {
+                    #[inline]
+                    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                        ::core::fmt::Formatter::debug_struct_field1_finish(f, "S", "a", &&self.a)
+                    }
+                }
+                #[automatically_derived]
+                #[allow(dead_code)]
+                impl ::core::cmp::Eq for S {
+                    #[inline]
+                    #[doc(hidden)]
+                    #[coverage(off)]
+                    fn assert_fields_are_eq(&self) {
+                        let _: ::core::cmp::AssertParamIsEq<u8>;
+                    }
+                }
+                pub struct T {
+                    b: u8,
+                }
+                #[automatically_derived]
+                #[allow(unused_qualifications)]
+                impl ::core::fmt::Debug for T {
+                    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                        ::core::fmt::Formatter::debug_struct_field1_finish(f, "T", "b", &&self.b)
+                    }
+                }
+                #[allow(non_upper_case_globals, clippy::all)]
+                const _: () = {};
+            "#,
+        )
+        .unwrap();
+        let names: Vec<String> = DEFAULT_STRIP_ATTRS
+            .iter()
+            .map(|name| (*name).to_owned())
+            .collect();
+        strip_attrs(&mut syntax_tree, &names);
+
+        let expected = tokens(
+            r#"
+                #[allow(dead_code)]
+                pub struct S {
+                    a: u8,
+                }
+                #[allow(dead_code)]
+                impl ::core::fmt::Debug for S {
+                    #[inline]
+                    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                        ::core::fmt::Formatter::debug_struct_field1_finish(f, "S", "a", &&self.a)
+                    }
+                }
+                #[allow(dead_code)]
+                impl ::core::cmp::Eq for S {
+                    #[inline]
+                    #[coverage(off)]
+                    fn assert_fields_are_eq(&self) {
+                        let _: ::core::cmp::AssertParamIsEq<u8>;
+                    }
+                }
+                pub struct T {
+                    b: u8,
+                }
+                impl ::core::fmt::Debug for T {
+                    fn fmt(&self, f: &mut ::core::fmt::Formatter) -> ::core::fmt::Result {
+                        ::core::fmt::Formatter::debug_struct_field1_finish(f, "T", "b", &&self.b)
+                    }
+                }
+                const _: () = {};
+            "#,
+        );
+        assert_eq!(syntax_tree.to_token_stream().to_string(), expected);
+    }
+
+    #[test]
+    fn strip_attrs_keeps_partly_matching_lint_lists() {
+        let mut syntax_tree = syn::parse_file(
+            r#"
+                #[allow(unused, dead_code)]
+                pub fn f() {}
+                #[allow(clippy::pedantic)]
+                pub fn g() {}
+            "#,
+        )
+        .unwrap();
+        strip_attrs(&mut syntax_tree, &["allow(unused)".to_owned()]);
+        let expected = tokens(
+            r#"
+                #[allow(unused, dead_code)]
+                pub fn f() {}
+                #[allow(clippy::pedantic)]
+                pub fn g() {}
+            "#,
+        );
+        assert_eq!(syntax_tree.to_token_stream().to_string(), expected);
+    }
+}
@@ -1,9 +1,17 @@
 use std::fmt::{self, Display};
 use std::io;
 
+#[derive(Debug)]
 pub enum Error {
     Io(io::Error),
     Toml(toml::ser::Error),
+    TomlDe(toml::de::Error),
+    Json(serde_json::Error),
+    Metadata(String),
+    Git(String),
+    // Expansion failed after reporting why on stderr; holds the exit code the
+    // command line tool would exit with.
+    Failed(i32),
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
@@ -20,6 +28,18 @@ impl From<toml::ser::Error> for Error {
     }
 }
 
+impl From<toml::de::Error> for Error {
+    fn from(error: toml::de::Error) -> Self {
+        Error::TomlDe(error)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::Json(error)
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
         use self::Error::*;
@@ -27,6 +47,13 @@ impl Display for Error {
         match self {
             Io(e) => e.fmt(formatter),
             Toml(e) => e.fmt(formatter),
+            TomlDe(e) => e.fmt(formatter),
+            Json(e) => e.fmt(formatter),
+            Metadata(msg) => write!(formatter, "cargo metadata: {}", msg),
+            Git(msg) => write!(formatter, "git: {}", msg),
+            Failed(code) => write!(formatter, "expansion failed with exit code {}", code),
         }
     }
 }
+
+impl std::error::Error for Error {}
@@ -1,10 +1,20 @@
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use serde::Serialize;
+use toml::value::Table;
 
 use crate::error::Result;
 
+#[derive(Debug, Clone)]
+pub enum Formatter {
+    Rustfmt(PathBuf),
+    Prettyplease,
+    Json,
+    // Tokens printed on a single line, without formatting.
+    Ugly,
+}
+
 #[derive(Serialize)]
 struct Rustfmt {
     normalize_doc_attributes: bool,
@@ -12,17 +22,79 @@ struct Rustfmt {
     reorder_modules: bool,
 }
 
-pub fn write_rustfmt_config(outdir: impl AsRef<Path>) -> Result<()> {
-    let config = Rustfmt {
+// The generated settings keep doc comments as `///` and leave the order of
+// imports and modules as the compiler wrote them. A user's config is merged on
+// top, so that its settings win, and --max-width over both.
+pub fn write_rustfmt_config(
+    outdir: impl AsRef<Path>,
+    user_config: Option<&Path>,
+    max_width: Option<usize>,
+) -> Result<()> {
+    let rustfmt_config_path = outdir.as_ref().join("rustfmt.toml");
+
+    let defaults = Rustfmt {
         normalize_doc_attributes: true,
         reorder_imports: false,
         reorder_modules: false,
     };
+    let mut config = match toml::Value::try_from(&defaults)? {
+        toml::Value::Table(config) => config,
+        _ => unreachable!(),
+    };
 
-    let toml_string = toml::to_string(&config)?;
+    if let Some(user_config) = user_config {
+        let content = fs::read(user_config)?;
+        let user_config: Table = toml::from_slice(&content)?;
+        config.extend(user_config);
+    }
+    if let Some(max_width) = max_width {
+        config.insert("max_width".to_owned(), toml::Value::from(max_width as i64));
+    }
 
-    let rustfmt_config_path = outdir.as_ref().join("rustfmt.toml");
+    let toml_string = toml::to_string(&config)?;
     fs::write(rustfmt_config_path, toml_string)?;
 
     Ok(())
 }
+
+// Nearest rustfmt.toml or .rustfmt.toml in the given directory or any of its
+// ancestors, the same lookup rustfmt does for the files it formats.
+pub fn find_rustfmt_config(dir: &Path) -> Option<PathBuf> {
+    let config_names = ["rustfmt.toml", ".rustfmt.toml"];
+    dir.ancestors().find_map(|dir| {
+        config_names
+            .iter()
+            .map(|name| dir.join(name))
+            .find(|path| path.is_file())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn written_config(user_config: &str, max_width: Option<usize>) -> Table {
+        let dir = tempfile::tempdir().unwrap();
+        let user_config_path = dir.path().join("user.toml");
+        fs::write(&user_config_path, user_config).unwrap();
+        write_rustfmt_config(dir.path(), Some(&user_config_path), max_width).unwrap();
+        let content = fs::read(dir.path().join("rustfmt.toml")).unwrap();
+        toml::from_slice(&content).unwrap()
+    }
+
+    #[test]
+    fn empty_user_config_keeps_defaults() {
+        let config = written_config("", None);
+        assert_eq!(config["normalize_doc_attributes"].as_bool(), Some(true));
+        assert_eq!(config["reorder_imports"].as_bool(), Some(false));
+        assert_eq!(config["reorder_modules"].as_bool(), Some(false));
+    }
+
+    #[test]
+    fn user_config_overrides_defaults() {
+        let config = written_config("reorder_imports = true\nmax_width = 80\n", Some(120));
+        assert_eq!(config["normalize_doc_attributes"].as_bool(), Some(true));
+        assert_eq!(config["reorder_imports"].as_bool(), Some(true));
+        assert_eq!(config["max_width"].as_integer(), Some(120));
+    }
+}
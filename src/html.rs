@@ -0,0 +1,60 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+use syntect::highlighting::ThemeSet;
+use syntect::html;
+use syntect::parsing::SyntaxSet;
+
+// Used when no theme is selected, or the selected one is not among syntect's
+// built-in themes.
+const DEFAULT_THEME: &str = "InspiredGitHub";
+
+// Highlight the content as a <pre> block with inline styles.
+pub fn render(content: &str, extension: &str, theme: Option<&str>, quiet: bool) -> String {
+    let syntax_set = SyntaxSet::load_defaults_newlines();
+    let syntax = syntax_set
+        .find_syntax_by_extension(extension)
+        .unwrap_or_else(|| syntax_set.find_syntax_plain_text());
+
+    if let Some(path) = theme.map(Path::new).filter(|path| path.is_file()) {
+        match ThemeSet::get_theme(path) {
+            Ok(theme) => {
+                return html::highlighted_html_for_string(content, &syntax_set, syntax, &theme)
+            }
+            Err(err) => {
+                if !quiet {
+                    let _ = writeln!(
+                        io::stderr(),
+                        "WARNING: failed to load theme {}: {}, using {}",
+                        path.display(),
+                        err,
+                        DEFAULT_THEME,
+                    );
+                }
+            }
+        }
+    }
+
+    let mut theme_set = ThemeSet::load_defaults();
+    let theme = match theme {
+        Some(name) if Path::new(name).is_file() => DEFAULT_THEME,
+        Some(name) if theme_set.themes.contains_key(name) => name,
+        Some("auto") | None => DEFAULT_THEME,
+        Some(_) if quiet => DEFAULT_THEME,
+        Some(name) => {
+            let mut names: Vec<&str> = theme_set.themes.keys().map(String::as_str).collect();
+            names.sort_unstable();
+            let _ = writeln!(
+                io::stderr(),
+                "WARNING: theme `{}` is not available for HTML output, using {}; choose one of: {}",
+                name,
+                DEFAULT_THEME,
+                names.join(", "),
+            );
+            DEFAULT_THEME
+        }
+    };
+    let theme = theme_set.themes.remove(theme).unwrap();
+
+    html::highlighted_html_for_string(content, &syntax_set, syntax, &theme)
+}
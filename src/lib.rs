@@ -0,0 +1,3083 @@
+#![allow(
+    clippy::enum_glob_use,
+    clippy::manual_strip,
+    clippy::match_like_matches_macro,
+    clippy::needless_pass_by_value,
+    clippy::non_ascii_literal,
+    clippy::struct_excessive_bools,
+    clippy::too_many_lines,
+    clippy::trivially_copy_pass_by_ref
+)]
+
+mod argfile;
+mod cache;
+mod cmd;
+mod completions;
+mod config;
+mod context;
+mod diff;
+mod doctest;
+mod edit;
+mod error;
+mod fmt;
+mod html;
+mod lint;
+mod locate;
+mod manifest;
+mod message;
+mod metadata;
+mod normalize;
+mod opts;
+mod parallel;
+mod since;
+mod timings;
+mod tmtheme;
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use atty::Stream::{Stderr, Stdout};
+use bat::assets::HighlightingAssets;
+use bat::config::Config as BatConfig;
+use bat::controller::Controller;
+use bat::input::Input;
+use bat::style::{StyleComponent, StyleComponents};
+use bat::{PagingMode, PrettyPrinter, WrappingMode};
+use console::Term;
+use notify::{EventKind, RecursiveMode, Watcher};
+use quote::quote;
+use structopt::StructOpt;
+use tempfile::TempDir;
+use termcolor::{Color::Green, ColorChoice, ColorSpec, StandardStream, WriteColor};
+
+use crate::cache::Cache;
+use crate::cmd::Line;
+use crate::config::Config;
+use crate::edit::Filter;
+use crate::fmt::Formatter;
+use crate::message::{Kind, Level};
+use crate::metadata::{LazyMetadata, Package, TargetKind};
+use crate::normalize::Normalizer;
+use crate::opts::Coloring::*;
+use crate::opts::Opts;
+use crate::since::Changes;
+use crate::timings::Phase;
+
+pub use crate::error::{Error, Result};
+pub use crate::opts::{
+    Args, Coloring, Edition, Formatting, LineEnding, MessageFormat, Paging, Palette, Wrapping,
+};
+
+/// Expands the crate selected by `args` the same way as `cargo expand` does,
+/// and returns the expanded code instead of printing it.
+///
+/// Options that only affect how the code is displayed, like `--theme` or
+/// `--output`, are ignored. Cargo's messages are still written to stderr. The
+/// cargo on the PATH, or in the `CARGO` environment variable, needs to be a
+/// nightly one; unlike the command line tool, this does not switch to a
+/// nightly toolchain by itself.
+///
+/// ```no_run
+/// use cargo_expand::Args;
+/// use structopt::StructOpt;
+///
+/// let args = Args::from_iter(&["expand", "--lib", "--ugly"]);
+/// let expanded = cargo_expand::expand(args)?;
+/// # Ok::<(), cargo_expand::Error>(())
+/// ```
+pub fn expand(mut args: Args) -> Result<String> {
+    let (config, mut metadata) = setup(&mut args)?;
+    let formatter = formatter(&args);
+    let color = get_color(&args, &config);
+    select_target(&mut args, &mut metadata, &color)?;
+
+    // Without a formatter, --format=none, the content is expanded unprocessed.
+    match produce(&args, &mut metadata, formatter.as_ref(), &color)? {
+        Expansion::Content(content, 0) => Ok(content),
+        Expansion::Content(_, code) | Expansion::Failed(code) => Err(Error::Failed(code)),
+    }
+}
+
+#[doc(hidden)]
+pub fn main() -> i32 {
+    match cargo_expand_or_run_nightly() {
+        Ok(code) => code,
+        // Already reported.
+        Err(Error::Failed(code)) => code,
+        Err(err) => {
+            let _ = writeln!(io::stderr(), "{}", err);
+            1
+        }
+    }
+}
+
+fn cargo_expand_or_run_nightly() -> Result<i32> {
+    let mut argv = argfile::expand(env::args_os())?;
+    let plus_toolchain = take_plus_toolchain(&mut argv);
+    let Opts::Expand(mut args) = Opts::from_iter(&argv);
+    if plus_toolchain.is_some() {
+        // Like `cargo +toolchain`, takes precedence over the environment.
+        args.toolchain = plus_toolchain;
+    }
+    let toolchain = toolchain(&args);
+
+    if env::var_os(NO_RUN_NIGHTLY).is_some()
+        || (args.toolchain.is_none() && maybe_nightly())
+        || !can_run_plus_toolchain(toolchain)
+    {
+        let timings = args.timings;
+        let result = cargo_expand(args);
+        if timings {
+            timings::print();
+        }
+        return result;
+    }
+
+    let mut nightly = nightly_command(toolchain, argv);
+    // For finding the same toolchain's rustfmt.
+    nightly.env("CARGO_EXPAND_TOOLCHAIN", toolchain);
+    let status = nightly.status()?;
+
+    Ok(match status.code() {
+        Some(code) => code,
+        None => {
+            if status.success() {
+                0
+            } else {
+                1
+            }
+        }
+    })
+}
+
+const NO_RUN_NIGHTLY: &str = "CARGO_EXPAND_NO_RUN_NIGHTLY";
+
+// Removes a `+toolchain` argument right after the subcommand, as in `cargo
+// expand +nightly-2023-01-01`, and returns the toolchain.
+fn take_plus_toolchain(argv: &mut Vec<OsString>) -> Option<String> {
+    let index = if argv.get(1).map_or(false, |arg| arg == "expand") {
+        2
+    } else {
+        1
+    };
+    let toolchain = argv.get(index)?.to_str()?;
+    if toolchain.len() > 1 && toolchain.starts_with('+') {
+        let toolchain = toolchain[1..].to_owned();
+        argv.remove(index);
+        Some(toolchain)
+    } else {
+        None
+    }
+}
+
+// The command that re-runs cargo expand under the given toolchain, from this
+// process's argv as invoked by cargo, i.e. `cargo-expand expand ...`.
+fn nightly_command(toolchain: &str, argv: impl IntoIterator<Item = OsString>) -> Command {
+    let mut nightly = Command::new("cargo");
+    nightly.arg(format!("+{}", toolchain));
+    nightly.arg("expand");
+
+    // Forwards every flag, including --frozen, --locked and --offline. Only
+    // the one `expand` inserted by cargo is stripped, so that an `expand`
+    // further along is forwarded like any other argument.
+    let mut args = argv.into_iter().skip(1).peekable();
+    if args.peek().map_or(false, |arg| arg == "expand") {
+        args.next();
+    }
+    nightly.args(args);
+
+    // The re-run process expands without checking for nightly again, so this
+    // is what prevents an infinite re-run loop.
+    nightly.env(NO_RUN_NIGHTLY, "");
+
+    nightly
+}
+
+fn toolchain(args: &Args) -> &str {
+    args.toolchain.as_deref().unwrap_or("nightly")
+}
+
+fn maybe_nightly() -> bool {
+    !definitely_not_nightly()
+}
+
+fn definitely_not_nightly() -> bool {
+    let mut cmd = Command::new(cargo_binary());
+    cmd.arg("--version");
+
+    let output = match cmd.output() {
+        Ok(output) => output,
+        Err(_) => return false,
+    };
+
+    let version = match String::from_utf8(output.stdout) {
+        Ok(version) => version,
+        Err(_) => return false,
+    };
+
+    version.starts_with("cargo 1") && !version.contains("nightly")
+}
+
+fn can_run_plus_toolchain(toolchain: &str) -> bool {
+    Command::new("cargo")
+        .arg(format!("+{}", toolchain))
+        .arg("--version")
+        .output()
+        .map_or(false, |output| output.status.success())
+}
+
+fn cargo_binary() -> OsString {
+    env::var_os("CARGO").unwrap_or_else(|| "cargo".to_owned().into())
+}
+
+fn rustc_binary() -> OsString {
+    env::var_os("RUSTC").unwrap_or_else(|| "rustc".to_owned().into())
+}
+
+fn cargo_expand(mut args: Args) -> Result<i32> {
+    if let Some(shell) = args.completions {
+        let _ = write!(io::stdout(), "{}", completions::generate(shell));
+        return Ok(0);
+    }
+
+    let (config, mut metadata) = setup(&mut args)?;
+
+    if args.all_features && !args.features.is_empty() && !args.quiet {
+        let _ = writeln!(
+            io::stderr(),
+            "WARNING: --features has no effect with --all-features, which activates every feature",
+        );
+    }
+
+    if args.themes {
+        let preview = args.preview && use_color(&get_color(&args, &config));
+        for theme in HighlightingAssets::from_binary().themes() {
+            if preview {
+                print_theme_preview(theme);
+            } else {
+                let _ = writeln!(io::stdout(), "{}", theme);
+            }
+        }
+        return Ok(0);
+    }
+
+    if let Some(kind) = &args.complete_targets {
+        let package = metadata.package()?;
+        let mut stdout = io::stdout();
+        for target in &package.targets {
+            if target.kind().as_str() == kind {
+                let _ = writeln!(stdout, "{}", target.name);
+            }
+        }
+        return Ok(0);
+    }
+
+    if args.list_targets {
+        let package = metadata.package()?;
+        let kinds = [
+            TargetKind::Lib,
+            TargetKind::Bin,
+            TargetKind::Example,
+            TargetKind::Test,
+            TargetKind::Bench,
+        ];
+        let mut stdout = io::stdout();
+        for kind in &kinds {
+            let mut targets = package
+                .targets
+                .iter()
+                .filter(|target| target.kind() == *kind)
+                .peekable();
+            if targets.peek().is_none() {
+                continue;
+            }
+            let _ = writeln!(stdout, "{}:", kind.as_str());
+            for target in targets {
+                let _ = writeln!(stdout, "    {}", target.name);
+            }
+        }
+        return Ok(0);
+    }
+
+    let formatter = formatter(&args);
+
+    let color = get_color(&args, &config);
+    if args.verbose > 1 {
+        print_formatter(&args, formatter.as_ref(), &color);
+    }
+
+    select_target(&mut args, &mut metadata, &color)?;
+
+    if args.verbose > 0 && args.file.is_none() && !args.workspace {
+        if let Ok(package) = metadata.package() {
+            let features: Vec<String> = package.enabled_features(&args).into_iter().collect();
+            let features = if features.is_empty() {
+                "none".to_owned()
+            } else {
+                features.join(", ")
+            };
+            print_status("Features", &features, &color);
+        }
+    }
+
+    if let Some(git_ref) = args.since.clone() {
+        match since::changes(&args, &mut metadata, &git_ref)? {
+            Changes::Modules(modules) => args.item = modules,
+            Changes::Everything => {}
+            Changes::Nothing => {
+                if !args.quiet {
+                    let _ = writeln!(
+                        io::stderr(),
+                        "note: no modules of the crate changed since {}",
+                        git_ref,
+                    );
+                }
+                return Ok(0);
+            }
+        }
+    }
+
+    if args.explain {
+        return explain(&args, formatter.as_ref(), &color);
+    }
+
+    if args.check {
+        // Skip formatting, only the exit code matters.
+        return Ok(match run_expansion(&args, None, &color)? {
+            Expansion::Content(_, code) | Expansion::Failed(code) => code,
+        });
+    }
+
+    if !args.diff_features.is_empty() {
+        return diff_features(&args, &mut metadata, formatter.as_ref(), &color);
+    }
+
+    if args.feature_powerset {
+        return feature_powerset(&args, &mut metadata);
+    }
+
+    if args.watch {
+        return watch(&args, &mut metadata, formatter.as_ref(), &config, &color);
+    }
+
+    if formatter.is_none() && prints_unprocessed(&args, &config, &color) {
+        return expand_to_stdout(&args, &color);
+    }
+
+    let (content, code) = match produce(&args, &mut metadata, formatter.as_ref(), &color)? {
+        Expansion::Content(content, code) => (with_line_endings(&args, &content), code),
+        Expansion::Failed(code) => return Ok(code),
+    };
+
+    if let Some(snapshot) = &args.snapshot {
+        return Ok(match compare_snapshot(&args, snapshot, &content, &color) {
+            Ok(true) => code,
+            Ok(false) => 1,
+            Err(err) => {
+                let _ = writeln!(
+                    io::stderr(),
+                    "ERROR: failed to access snapshot {}: {}",
+                    snapshot.display(),
+                    err,
+                );
+                1
+            }
+        });
+    }
+
+    write_content(&args, formatter.as_ref(), &config, &color, content, code)
+}
+
+fn formatter(args: &Args) -> Option<Formatter> {
+    if args.raw {
+        return None;
+    }
+    if args.json {
+        return Some(Formatter::Json);
+    }
+
+    match formatting(args) {
+        Formatting::Rustfmt => match which_rustfmt(args) {
+            Some(rustfmt) => Some(Formatter::Rustfmt(rustfmt)),
+            None => {
+                if args.format.is_some() && !args.quiet {
+                    report(
+                        args,
+                        Level::Warning,
+                        Kind::RustfmtMissing,
+                        "rustfmt is not installed, formatting with prettyplease",
+                    );
+                }
+                // Fall back to prettyplease if rustfmt is not installed.
+                Some(Formatter::Prettyplease)
+            }
+        },
+        Formatting::Prettyplease => Some(Formatter::Prettyplease),
+        // Only parse the expansion when there is something to do with it.
+        Formatting::None if edits_tree(args) => Some(Formatter::Ugly),
+        Formatting::None => None,
+    }
+}
+
+// Setup that the command line tool and the library share: the directory form
+// of --manifest-path, the [expand] config, and then --use-target-dir.
+fn setup(args: &mut Args) -> Result<(Config, LazyMetadata)> {
+    if let Some(path) = args.manifest_path.clone() {
+        match manifest::resolve(&path) {
+            Ok(manifest_path) => args.manifest_path = Some(manifest_path),
+            Err(message) => {
+                let _ = writeln!(io::stderr(), "ERROR: {}", message);
+                return Err(Error::Failed(1));
+            }
+        }
+    }
+
+    let config = config::deserialize(project_dir(args).as_deref());
+    apply_config(args, &config);
+
+    let mut metadata = LazyMetadata::new(args);
+    if args.use_target_dir {
+        use_target_dir(args, &mut metadata)?;
+    }
+    Ok((config, metadata))
+}
+
+// Resolve --current-file, or CARGO_EXPAND_FILE, to the target it belongs to,
+// and check the selected target before running a build that would fail with a
+// less helpful message.
+fn select_target(args: &mut Args, metadata: &mut LazyMetadata, color: &Coloring) -> Result<()> {
+    if args.current_file.is_none() && !selects_what_to_expand(args) {
+        args.current_file = env::var_os("CARGO_EXPAND_FILE")
+            .filter(|file| !file.is_empty())
+            .map(PathBuf::from);
+    }
+    if let Some(file) = args.current_file.clone() {
+        match locate::owner(metadata, args.package.as_deref(), &file)? {
+            Some(owner) => {
+                match owner.kind {
+                    TargetKind::Lib => args.lib = true,
+                    TargetKind::Bin => args.bin = Some(owner.target),
+                    TargetKind::Example => args.example = Some(owner.target),
+                    TargetKind::Test => args.test = Some(owner.target),
+                    TargetKind::Bench => args.bench = Some(owner.target),
+                    TargetKind::CustomBuild => unreachable!(),
+                }
+                // The root file of the target is the whole target.
+                if !owner.module.is_empty() {
+                    args.item.extend(owner.module.join("::").parse().ok());
+                }
+                metadata.set_package(owner.package.clone());
+                args.package = Some(owner.package);
+            }
+            None => {
+                if !args.quiet {
+                    let _ = writeln!(
+                        io::stderr(),
+                        "WARNING: {} does not belong to any target of the package, expanding the whole package",
+                        file.display(),
+                    );
+                }
+            }
+        }
+    }
+
+    if args.file.is_none()
+        && !(check_target_names(args, metadata) && check_default_target(args, metadata, color))
+    {
+        return Err(Error::Failed(1));
+    }
+
+    // Rustfmt formats with the edition of the package being expanded. With
+    // --workspace that is up to each member.
+    if args.edition.is_none() && args.file.is_none() && !args.workspace {
+        args.edition = metadata.package().ok().and_then(Package::edition);
+    }
+    Ok(())
+}
+
+// Whether the command line names a target, file or item to expand. Only
+// otherwise does CARGO_EXPAND_FILE stand in for --current-file, so that an
+// editor exporting it does not get in the way of other runs.
+fn selects_what_to_expand(args: &Args) -> bool {
+    args.file.is_some()
+        || args.lib
+        || args.bin.is_some()
+        || args.example.is_some()
+        || args.test.is_some()
+        || args.tests
+        || args.bench.is_some()
+        || args.targets
+        || args.workspace
+        || args.doc
+        || !args.item.is_empty()
+        || args.item_regex.is_some()
+        || args.since.is_some()
+}
+
+fn formatting(args: &Args) -> Formatting {
+    match args.format {
+        Some(formatting) => formatting,
+        None if args.ugly => Formatting::None,
+        None if args.prettyplease => Formatting::Prettyplease,
+        None => Formatting::Rustfmt,
+    }
+}
+
+// With -vv, say how the output is going to be formatted, and why when it is not
+// what was asked for.
+fn print_formatter(args: &Args, formatter: Option<&Formatter>, color: &Coloring) {
+    let message = match formatter {
+        Some(Formatter::Rustfmt(rustfmt)) => {
+            let source = if env::var_os("RUSTFMT").is_some() {
+                "$RUSTFMT"
+            } else {
+                "the toolchain"
+            };
+            format!("rustfmt {} (from {})", rustfmt.display(), source)
+        }
+        Some(Formatter::Prettyplease) => match formatting(args) {
+            Formatting::Rustfmt => "prettyplease, rustfmt was not found".to_owned(),
+            _ => "prettyplease".to_owned(),
+        },
+        Some(Formatter::Json) => "none, printing the syntax tree as JSON".to_owned(),
+        Some(Formatter::Ugly) => "none, printing tokens unformatted".to_owned(),
+        None if args.raw => "none, printing rustc's output as it is".to_owned(),
+        None => "none, printing rustc's output unprocessed".to_owned(),
+    };
+    print_status("Formatter", &message, color);
+}
+
+// Print the commands that an expansion would run, without running them. The
+// rustfmt config is written out as usual, so that with --keep-temp the rustfmt
+// command works as printed.
+fn explain(args: &Args, formatter: Option<&Formatter>, color: &Coloring) -> Result<i32> {
+    let outdir = temp_dir(args)?;
+    let outfile_path = outdir.path().join("expanded");
+    let mut cmd;
+    if let Some(file) = &args.file {
+        cmd = Command::new(rustc_binary());
+        apply_file_args(&mut cmd, args, file, color, &outfile_path);
+    } else {
+        cmd = Command::new(cargo_binary());
+        apply_args(&mut cmd, args, color, &outfile_path);
+    }
+
+    if let Some(Formatter::Rustfmt(rustfmt)) = formatter {
+        let user_config = match &args.rustfmt_config {
+            Some(rustfmt_config) => Some(rustfmt_config.clone()),
+            None => project_dir(args).and_then(|dir| fmt::find_rustfmt_config(&dir)),
+        };
+        fmt::write_rustfmt_config(outdir.path(), user_config.as_deref(), args.max_width)?;
+        let edition = rustfmt_editions(args)[0];
+        print_command(rustfmt_line(rustfmt, edition, outdir.path()), color);
+    }
+
+    Ok(0)
+}
+
+// Where the finished content goes: the files of --split, the --output file, or
+// stdout.
+fn write_content(
+    args: &Args,
+    formatter: Option<&Formatter>,
+    config: &Config,
+    color: &Coloring,
+    content: String,
+    code: i32,
+) -> Result<i32> {
+    if let Some(dir) = &args.split {
+        return split(args, formatter, &content, dir, code, color);
+    }
+
+    let content = if let Html = color {
+        let theme = args.theme.as_ref().or(config.theme.as_ref());
+        let extension = if args.json { "json" } else { "rs" };
+        html::render(&content, extension, theme.map(String::as_str), args.quiet)
+    } else {
+        content
+    };
+
+    // Write to file
+    if let Some(output) = &args.output {
+        return Ok(match write_output(output, &content) {
+            Ok(()) => {
+                if !args.quiet {
+                    let _ = writeln!(io::stderr(), "Wrote {}", output.display());
+                }
+                code
+            }
+            Err(err) => {
+                let _ = writeln!(
+                    io::stderr(),
+                    "ERROR: failed to write {}: {}",
+                    output.display(),
+                    err,
+                );
+                1
+            }
+        });
+    }
+
+    // Run pretty printer
+    if !args.quiet {
+        let _ = writeln!(io::stderr());
+    }
+    print_content(&content, args, config, color);
+
+    Ok(code)
+}
+
+// Everything that goes into the content to print: running cargo or loading the
+// cache, and then the whole-file transformations.
+fn produce(
+    args: &Args,
+    metadata: &mut LazyMetadata,
+    formatter: Option<&Formatter>,
+    color: &Coloring,
+) -> Result<Expansion> {
+    if args.in_context && args.item.len() != 1 {
+        let _ = writeln!(
+            io::stderr(),
+            "ERROR: --in-context takes exactly one item path."
+        );
+        return Ok(Expansion::Failed(1));
+    }
+
+    let cache = if args.cache {
+        match Cache::new(args, formatter, metadata) {
+            Ok(cache) => Some(cache),
+            Err(err) => {
+                if !args.quiet {
+                    let _ = writeln!(io::stderr(), "WARNING: not using the cache: {}", err);
+                }
+                None
+            }
+        }
+    } else {
+        None
+    };
+
+    let normalizer = if args.cache || args.snapshot.is_some() {
+        Some(Normalizer::new(args, metadata))
+    } else {
+        None
+    };
+    let normalize = |content: String| match &normalizer {
+        Some(normalizer) => normalizer.apply(&content),
+        None => content,
+    };
+
+    let mut code = 0;
+    let content = if args.targets {
+        let (content, targets_code) = expand_targets(args, metadata, formatter, color)?;
+        code = targets_code;
+        normalize(content)
+    } else if args.workspace {
+        match expand_workspace(args, metadata, formatter, color)? {
+            Expansion::Content(content, workspace_code) => {
+                code = workspace_code;
+                normalize(content)
+            }
+            Expansion::Failed(code) => return Ok(Expansion::Failed(code)),
+        }
+    } else if args.doc {
+        match expand_doctests(args, metadata, formatter, color)? {
+            Expansion::Content(content, doc_code) => {
+                code = doc_code;
+                normalize(content)
+            }
+            Expansion::Failed(code) => return Ok(Expansion::Failed(code)),
+        }
+    } else if let Some(content) = cache.as_ref().and_then(Cache::load) {
+        content
+    } else if args.dump_unexpanded {
+        match dump_unexpanded(args, metadata, formatter, color)? {
+            Expansion::Content(content, _) => normalize(content),
+            Expansion::Failed(code) => return Ok(Expansion::Failed(code)),
+        }
+    } else {
+        match run_expansion(args, formatter, color)? {
+            Expansion::Content(content, 0) => {
+                let content = normalize(content);
+                if let Some(cache) = &cache {
+                    if let Err(err) = cache.store(&content) {
+                        if !args.quiet {
+                            let _ =
+                                writeln!(io::stderr(), "WARNING: failed to write cache: {}", err);
+                        }
+                    }
+                }
+                content
+            }
+            Expansion::Content(content, cargo_code) => {
+                code = cargo_code;
+                normalize(content)
+            }
+            Expansion::Failed(code) => return Ok(Expansion::Failed(code)),
+        }
+    };
+
+    if args.keep_going {
+        code = 0;
+    }
+
+    let content = if args.in_context {
+        let item = &args.item[0];
+        match context::splice(args, metadata, item, &content)? {
+            Some(content) => content,
+            None => {
+                let _ = writeln!(
+                    io::stderr(),
+                    "ERROR: could not find {} in the unexpanded source",
+                    item,
+                );
+                return Ok(Expansion::Failed(1));
+            }
+        }
+    } else {
+        content
+    };
+
+    let content = match &args.resolve_crate {
+        Some(name) => content.replace("$crate", name),
+        None => {
+            // Without sanitizing, macro_rules definitions are left in and
+            // legitimately contain $crate.
+            let sanitized = formatter.is_some() && !args.no_sanitize;
+            if sanitized && content.contains("$crate") && !args.quiet {
+                let _ = writeln!(
+                    io::stderr(),
+                    "WARNING: the output contains `$crate`, which macros use to refer to the \
+                     crate they were defined in and which is not valid outside of a macro; \
+                     pass --resolve-crate to replace it",
+                );
+            }
+            content
+        }
+    };
+
+    Ok(Expansion::Content(content, code))
+}
+
+// Whether the expansion goes to stdout exactly as rustc writes it, with no
+// formatting or any other processing.
+fn prints_unprocessed(args: &Args, config: &Config, color: &Coloring) -> bool {
+    let theme = theme(args, config);
+    !args.targets
+        && !args.workspace
+        && !args.doc
+        && !args.cache
+        && !args.dump_unexpanded
+        && !args.in_context
+        && args.resolve_crate.is_none()
+        && args.snapshot.is_none()
+        && args.split.is_none()
+        && args.line_ending.is_none()
+        && args.output.is_none()
+        && !matches!(color, Html)
+        && !highlights(args, theme, color)
+}
+
+fn strip_attr_names(names: &[String]) -> Vec<String> {
+    if names.is_empty() {
+        edit::DEFAULT_STRIP_ATTRS
+            .iter()
+            .map(|name| (*name).to_owned())
+            .collect()
+    } else if names.iter().any(|name| name == "none") {
+        Vec::new()
+    } else {
+        names.to_vec()
+    }
+}
+
+fn item_filters(args: &Args) -> Vec<Filter> {
+    let mut filters: Vec<Filter> = args.item.iter().cloned().map(Filter::Path).collect();
+    filters.extend(args.item_regex.clone().map(Filter::Regex));
+    filters
+}
+
+fn print_content(content: &str, args: &Args, config: &Config, color: &Coloring) {
+    let content = &if args.raw {
+        content.to_owned()
+    } else {
+        with_trailing_newline(content)
+    };
+    let theme = theme(args, config);
+    if highlights(args, theme, color) {
+        let language = language(args);
+        // The name shown in the header.
+        let name = match &args.file {
+            Some(file) => file.file_name().map_or_else(|| file.clone(), PathBuf::from),
+            None => PathBuf::from("expanded.rs"),
+        };
+
+        // A theme may also be the path of a .tmTheme file, which bat's
+        // PrettyPrinter cannot load, so this drives bat's Controller directly.
+        let mut assets = None;
+        let mut theme = theme.map(str::to_owned);
+        if let Some(path) = theme.as_ref().map(Path::new).filter(|path| path.is_file()) {
+            match tmtheme::load(path) {
+                Ok((custom, name)) => {
+                    assets = Some(custom);
+                    theme = Some(name);
+                }
+                Err(err) => {
+                    if !args.quiet {
+                        let _ = writeln!(
+                            io::stderr(),
+                            "WARNING: failed to load theme {}: {}",
+                            path.display(),
+                            err,
+                        );
+                    }
+                    theme = None;
+                }
+            }
+        }
+        let assets = assets.unwrap_or_else(HighlightingAssets::from_binary);
+
+        // Bat can only map a theme onto the terminal's own colors if the theme
+        // is written that way, which is what its ansi theme is for.
+        let palette = args.color_palette.unwrap_or(Palette::Ansi256);
+        if let Palette::Ansi16 = palette {
+            let chosen = args.theme.as_ref().or(config.theme.as_ref());
+            if let Some(chosen) = chosen.filter(|chosen| *chosen != "ansi" && *chosen != "auto") {
+                if !args.quiet {
+                    let _ = writeln!(
+                        io::stderr(),
+                        "WARNING: --color-palette=16 highlights with the `ansi` theme instead of `{}`",
+                        chosen,
+                    );
+                }
+            }
+            theme = Some("ansi".to_owned());
+        }
+
+        let paging = paging(args, config);
+        if args.verbose > 1 {
+            let name = theme.as_deref().unwrap_or("default");
+            print_status("Theme", name, color);
+            let message = match paging {
+                Paging::Auto => "when the output does not fit on one screen",
+                Paging::Always => "always",
+                Paging::Never => "off",
+            };
+            print_status("Paging", message, color);
+        }
+
+        let mut components = Vec::new();
+        if args.grid {
+            components.push(StyleComponent::Grid);
+        }
+        if args.header {
+            components.push(StyleComponent::Header);
+        }
+        if args.line_numbers || config.line_numbers == Some(true) {
+            components.push(StyleComponent::LineNumbers);
+        }
+        let bat_config = BatConfig {
+            language: Some(language),
+            term_width: Term::stdout().size().1 as usize,
+            tab_width: args.tab_width.or(config.tab_width).unwrap_or(4),
+            colored_output: true,
+            true_color: matches!(palette, Palette::TrueColor),
+            style_components: StyleComponents::new(&components),
+            wrapping_mode: match args.wrap {
+                Some(Wrapping::Character) => WrappingMode::Character,
+                // Tells bat to have less chop long lines rather than wrap them.
+                Some(Wrapping::Never) => WrappingMode::NoWrapping(true),
+                None => WrappingMode::default(),
+            },
+            paging_mode: match paging {
+                Paging::Auto => PagingMode::QuitIfOneScreen,
+                Paging::Always => PagingMode::Always,
+                Paging::Never => PagingMode::Never,
+            },
+            theme: theme.unwrap_or_default(),
+            pager: args.pager_cmd.as_deref(),
+            ..BatConfig::default()
+        };
+        let input = Input::from_reader(Box::new(content.as_bytes())).with_name(Some(name));
+
+        // Ignore any errors.
+        let controller = Controller::new(&bat_config, &assets);
+        let _ = timings::record(Phase::Print, || controller.run(vec![input]));
+    } else {
+        if args.verbose > 1 {
+            print_status("Theme", "none, the output is not highlighted", color);
+        }
+        let _ = timings::record(Phase::Print, || write!(io::stdout(), "{}", content));
+    }
+}
+
+// Whether or not the formatter left any, end with exactly one newline, so that
+// shell captures and snapshots see the same output either way.
+fn with_trailing_newline(content: &str) -> String {
+    let newline = if content.contains("\r\n") {
+        "\r\n"
+    } else {
+        "\n"
+    };
+    let mut content = content
+        .trim_end_matches(|ch| ch == '\r' || ch == '\n')
+        .to_owned();
+    if !content.is_empty() {
+        content += newline;
+    }
+    content
+}
+
+// Consistent line endings, as chosen by --line-ending, in place of whatever mix
+// the compiler, rustfmt and our own edits produced. Left alone with --raw.
+fn with_line_endings(args: &Args, content: &str) -> String {
+    if args.raw {
+        return content.to_owned();
+    }
+    let crlf = match args.line_ending.unwrap_or(LineEnding::Native) {
+        LineEnding::Lf => false,
+        LineEnding::Crlf => true,
+        LineEnding::Native => cfg!(windows),
+    };
+    let content = content.replace("\r\n", "\n");
+    if crlf {
+        content.replace('\n', "\r\n")
+    } else {
+        content
+    }
+}
+
+fn theme<'a>(args: &'a Args, config: &'a Config) -> Option<&'a str> {
+    match args.theme.as_ref().or(config.theme.as_ref()) {
+        Some(theme) if theme == "auto" => auto_theme(),
+        Some(theme) => Some(theme.as_str()),
+        None => auto_theme(),
+    }
+}
+
+// Whether print_content will run the output through bat.
+fn highlights(args: &Args, theme: Option<&str>, color: &Coloring) -> bool {
+    let none_theme = theme == Some("none");
+    match color {
+        _ if args.json => false,
+        Always => true,
+        Never | Html => false,
+        Auto => !none_theme && atty::is(Stdout),
+    }
+}
+
+// The language requested by --language if bat knows it, or else Rust.
+fn language(args: &Args) -> &str {
+    let language = match &args.language {
+        Some(language) => language,
+        None => return "rust",
+    };
+    let assets = HighlightingAssets::from_binary();
+    let known = assets.syntaxes().iter().any(|syntax| {
+        syntax.name.eq_ignore_ascii_case(language)
+            || syntax.file_extensions.iter().any(|ext| ext == language)
+    });
+    if known {
+        language
+    } else {
+        if !args.quiet {
+            let _ = writeln!(
+                io::stderr(),
+                "WARNING: unknown language `{}`, highlighting as Rust",
+                language,
+            );
+        }
+        "rust"
+    }
+}
+
+// Pick a theme suited to the terminal background, as reported by the
+// COLORFGBG variable that many terminals set to "fg;bg" using ANSI color
+// indices. Returns None if the background is unknown.
+fn auto_theme() -> Option<&'static str> {
+    let colorfgbg = env::var("COLORFGBG").ok()?;
+    let bg: u8 = colorfgbg.rsplit(';').next()?.parse().ok()?;
+    match bg {
+        0..=6 | 8 => Some("Monokai Extended"),
+        7 | 9..=15 => Some("Monokai Extended Light"),
+        _ => None,
+    }
+}
+
+// Scratch directory for cargo's output and the rustfmt config. It is removed
+// when dropped, unless --keep-temp was passed.
+struct OutDir {
+    path: PathBuf,
+    // None if the directory is being kept.
+    _temp_dir: Option<TempDir>,
+}
+
+impl OutDir {
+    fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+// Put temporary files in the target directory for --use-target-dir.
+fn use_target_dir(args: &mut Args, metadata: &mut LazyMetadata) -> Result<()> {
+    let target_dir = match &args.target_dir {
+        Some(dir) => dir.clone(),
+        None => metadata.get()?.target_directory.clone(),
+    };
+    let dir = target_dir.join("cargo-expand");
+    fs::create_dir_all(&dir)?;
+    args.temp_dir = Some(dir);
+    Ok(())
+}
+
+fn temp_dir(args: &Args) -> io::Result<OutDir> {
+    let mut builder = tempfile::Builder::new();
+    builder.prefix("cargo-expand");
+    let dir = match &args.temp_dir {
+        Some(dir) => dir.clone(),
+        None => env::temp_dir(),
+    };
+    let temp_dir = builder.tempdir_in(dir).map_err(|err| {
+        let message = format!("failed to create temporary directory: {}", err);
+        io::Error::new(err.kind(), message)
+    })?;
+
+    if args.keep_temp {
+        let path = temp_dir.into_path();
+        let _ = writeln!(
+            io::stderr(),
+            "Keeping temporary files in {}",
+            path.display()
+        );
+        Ok(OutDir {
+            path,
+            _temp_dir: None,
+        })
+    } else {
+        Ok(OutDir {
+            path: temp_dir.path().to_owned(),
+            _temp_dir: Some(temp_dir),
+        })
+    }
+}
+
+enum Expansion {
+    // Expanded code along with the exit code of cargo, which is nonzero if the
+    // crate failed to compile after expansion, e.g. because of type errors.
+    Content(String, i32),
+    // Exit code to return when no expanded output was produced.
+    Failed(i32),
+}
+
+// Run cargo followed by the formatter, producing the expanded and formatted
+// code.
+fn run_expansion(
+    args: &Args,
+    formatter: Option<&Formatter>,
+    color: &Coloring,
+) -> Result<Expansion> {
+    let outdir = temp_dir(args)?;
+    let output = CargoRun::new(args, color, outdir.path()).run()?;
+    format_output(args, formatter, &outdir, output, color)
+}
+
+// Format the expansion that cargo wrote into outdir.
+fn format_output(
+    args: &Args,
+    formatter: Option<&Formatter>,
+    outdir: &OutDir,
+    output: CargoOutput,
+    color: &Coloring,
+) -> Result<Expansion> {
+    let code = match output {
+        CargoOutput::Written(code) => code,
+        CargoOutput::Failed(code) => return Ok(Expansion::Failed(code)),
+    };
+
+    let content = fs::read_to_string(outdir.path().join("expanded"))?;
+    timings::record(Phase::Format, || {
+        format(args, formatter, outdir.path(), content, code, color)
+    })
+}
+
+// Copy the expansion to stdout as it is written by rustc, without reading all
+// of it into memory, for when it is printed without any processing.
+fn expand_to_stdout(args: &Args, color: &Coloring) -> Result<i32> {
+    let outdir = temp_dir(args)?;
+    let code = match CargoRun::new(args, color, outdir.path()).run()? {
+        CargoOutput::Written(code) => code,
+        CargoOutput::Failed(code) => return Ok(code),
+    };
+
+    if !args.quiet {
+        let _ = writeln!(io::stderr());
+    }
+    let mut file = fs::File::open(outdir.path().join("expanded"))?;
+    let stdout = io::stdout();
+    let _ = timings::record(Phase::Print, || io::copy(&mut file, &mut stdout.lock()));
+
+    Ok(if args.keep_going { 0 } else { code })
+}
+
+enum CargoOutput {
+    // Cargo's exit code, which is nonzero if the crate failed to compile
+    // after expansion.
+    Written(i32),
+    // Exit code to return when no expanded output was produced.
+    Failed(i32),
+}
+
+// An invocation of cargo, or of rustc directly on a standalone file, that
+// writes the expansion to a file named `expanded` in the output directory.
+// It carries everything it needs so that it can be run on another thread.
+struct CargoRun {
+    cmd: Command,
+    outfile_path: PathBuf,
+    show_warnings: bool,
+    hide_warnings: bool,
+    buffered: bool,
+    build_target: Option<config::BuildTarget>,
+    message_format: MessageFormat,
+    lock_timeout: Option<Duration>,
+}
+
+impl CargoRun {
+    fn new(args: &Args, color: &Coloring, outdir: &Path) -> Self {
+        let outfile_path = outdir.join("expanded");
+
+        // Cargo silently picks up a default target from its configuration.
+        let build_target = if args.target.is_none() && args.file.is_none() {
+            config::build_target(args)
+        } else {
+            None
+        };
+        if args.verbose > 0 {
+            if let Some(build_target) = &build_target {
+                let message = format!("{} ({})", build_target.target, build_target.source);
+                print_status("Targeting", &message, color);
+            }
+        }
+
+        let mut cmd;
+        if let Some(file) = &args.file {
+            cmd = Command::new(rustc_binary());
+            apply_file_args(&mut cmd, args, file, color, &outfile_path);
+        } else {
+            cmd = Command::new(cargo_binary());
+            apply_args(&mut cmd, args, color, &outfile_path);
+        }
+
+        CargoRun {
+            cmd,
+            outfile_path,
+            show_warnings: args.show_warnings,
+            hide_warnings: args.no_warnings || args.quiet,
+            // Concurrent expansions hold on to their output until they are
+            // done, so that it is not interleaved.
+            buffered: args.parallel.map_or(false, |jobs| jobs > 1)
+                && (args.targets || args.workspace || args.doc || !args.diff_features.is_empty()),
+            build_target,
+            message_format: message_format(args),
+            lock_timeout: args.lock_timeout.map(Duration::from_secs),
+        }
+    }
+
+    fn run(mut self) -> Result<CargoOutput> {
+        let mut buffer = Vec::new();
+        let mut stderr = io::stderr();
+        let out: &mut dyn Write = if self.buffered {
+            &mut buffer
+        } else {
+            &mut stderr
+        };
+        let result = self.run_to(out);
+        let _ = io::stderr().write_all(&buffer);
+        result
+    }
+
+    fn run_to(&mut self, out: &mut dyn Write) -> Result<CargoOutput> {
+        let cmd = &mut self.cmd;
+        let show_warnings = self.show_warnings;
+        let hide_warnings = self.hide_warnings;
+        let lock_timeout = self.lock_timeout;
+        let code = timings::record(Phase::Cargo, || {
+            if show_warnings {
+                filter_err(cmd, out, lock_timeout, |_| false)
+            } else if hide_warnings {
+                let mut warnings = WarningFilter::default();
+                filter_err(cmd, out, lock_timeout, |line| {
+                    warnings.ignore(line) || ignore_cargo_err(line)
+                })
+            } else {
+                filter_err(cmd, out, lock_timeout, ignore_cargo_err)
+            }
+        })?;
+
+        if !self.outfile_path.exists() {
+            if code != 0 {
+                message::emit(
+                    out,
+                    self.message_format,
+                    Level::Error,
+                    Kind::CompileFailed,
+                    "expansion aborted: the crate failed to compile (see errors above)",
+                );
+                if let Some(build_target) = &self.build_target {
+                    if let MessageFormat::Human = self.message_format {
+                        let _ = writeln!(
+                            out,
+                            "note: the crate was built for {}, set by {}",
+                            build_target.target, build_target.source,
+                        );
+                    }
+                }
+                return Ok(CargoOutput::Failed(code));
+            }
+            return Ok(CargoOutput::Failed(1));
+        }
+
+        if fs::metadata(&self.outfile_path)?.len() == 0 {
+            message::emit(
+                out,
+                self.message_format,
+                Level::Error,
+                Kind::NoOutput,
+                "rustc produced no expanded output",
+            );
+            return Ok(CargoOutput::Failed(if code == 0 { 1 } else { code }));
+        }
+
+        Ok(CargoOutput::Written(code))
+    }
+}
+
+fn message_format(args: &Args) -> MessageFormat {
+    args.message_format.unwrap_or(MessageFormat::Human)
+}
+
+fn report(args: &Args, level: Level, kind: Kind, message: &str) {
+    message::emit(
+        &mut io::stderr(),
+        message_format(args),
+        level,
+        kind,
+        message,
+    );
+}
+
+// Parse and edit the code according to the filtering options, then run rustfmt
+// or prettyplease on it. The outdir is scratch space for rustfmt.
+fn format(
+    args: &Args,
+    formatter: Option<&Formatter>,
+    outdir: &Path,
+    mut content: String,
+    code: i32,
+    color: &Coloring,
+) -> Result<Expansion> {
+    // Run rustfmt or prettyplease
+    if let Some(formatter) = formatter {
+        content = insert_placeholders(&content);
+
+        force_fallback();
+
+        // Discard comments, which are misplaced by the compiler. Doc comments
+        // on items survive as #[doc] attributes.
+        let syntax_tree = syn::parse_file(&content);
+        let mut stats = None;
+        if let Err(err) = &syntax_tree {
+            let start = err.span().start();
+            let message = format!(
+                "failed to parse expanded output at line {}, column {}: {}",
+                start.line,
+                start.column + 1,
+                err,
+            );
+            if args.json {
+                report(args, Level::Error, Kind::ParseFailed, &message);
+                return Ok(Expansion::Failed(1));
+            }
+            if edits_tree(args) && !args.quiet {
+                let message = format!(
+                    "{}; the options that select or edit items were not applied",
+                    message,
+                );
+                report(args, Level::Warning, Kind::ParseFailed, &message);
+            } else if args.verbose > 0 {
+                report(args, Level::Warning, Kind::ParseFailed, &message);
+            }
+        }
+        if let Ok(mut syntax_tree) = syntax_tree {
+            if !args.no_sanitize {
+                edit::sanitize(&mut syntax_tree, args.keep_docs);
+            }
+            if args.inline_includes {
+                inline_includes(args, &mut syntax_tree);
+            }
+            if args.tidy_imports {
+                edit::tidy_imports(&mut syntax_tree);
+            }
+            if let Some(names) = &args.strip_attrs {
+                edit::strip_attrs(&mut syntax_tree, &strip_attr_names(names));
+            }
+            if let Some(module) = &args.module {
+                match edit::module_contents(module, &syntax_tree, args.flatten) {
+                    Some(items) => {
+                        syntax_tree.shebang = None;
+                        syntax_tree.attrs.clear();
+                        syntax_tree.items = items;
+                    }
+                    None => {
+                        let _ = writeln!(io::stderr(), "ERROR: no such module: {}", module);
+                        return Ok(Expansion::Failed(1));
+                    }
+                }
+            }
+            let exclude = edit::Exclude::new(&args.exclude_item, &syntax_tree);
+            let filters = item_filters(args);
+            if !filters.is_empty() {
+                syntax_tree.shebang = None;
+                syntax_tree.attrs.clear();
+                let (items, unmatched) = edit::select(&filters, &syntax_tree);
+                if !args.quiet {
+                    for filter in &unmatched {
+                        let _ = writeln!(io::stderr(), "WARNING: no such item: {}", filter);
+                    }
+                }
+                if items.is_empty() {
+                    return Ok(Expansion::Failed(1));
+                }
+                syntax_tree.items = items;
+            }
+            exclude.apply_to(&mut syntax_tree);
+            edit::retain_kinds(&mut syntax_tree, &args.only);
+            if args.sorted {
+                edit::sort_items(&mut syntax_tree);
+            }
+            if args.lint {
+                for finding in lint::check(&syntax_tree, DOLLAR_CRATE_PLACEHOLDER) {
+                    let _ = writeln!(
+                        io::stderr(),
+                        "WARNING: `{}` {} [{}]",
+                        finding.path,
+                        finding.message,
+                        finding.lint,
+                    );
+                }
+            }
+            if args.stats {
+                stats = Some(edit::stats(&syntax_tree));
+            }
+            content = unparse(formatter, &syntax_tree, args.compact);
+        }
+
+        content = finish(args, formatter, outdir, content, color)?;
+
+        if let Some(stats) = stats {
+            print_stats(&stats, &content);
+        }
+    }
+
+    Ok(Expansion::Content(content, code))
+}
+
+// Whether any of the options that need the parsed syntax tree are in use.
+fn edits_tree(args: &Args) -> bool {
+    !item_filters(args).is_empty()
+        || !args.exclude_item.is_empty()
+        || !args.only.is_empty()
+        || args.sorted
+        || args.tidy_imports
+        || args.strip_attrs.is_some()
+        || args.module.is_some()
+        || args.lint
+        || args.stats
+        || args.inline_includes
+}
+
+// Rustc inlines the files of the calls that it expands, so the calls that are
+// left are in the root file, as printed by --dump-unexpanded, and their paths
+// are relative to it.
+fn inline_includes(args: &Args, syntax_tree: &mut syn::File) {
+    let root = match &args.file {
+        Some(file) => Ok(file.clone()),
+        None => metadata::root_source(args, &mut LazyMetadata::new(args)),
+    };
+    let dir = match &root {
+        Ok(root) => root.parent().unwrap_or_else(|| Path::new("")),
+        Err(_) => return,
+    };
+    let limit = args.inline_limit.unwrap_or(16384);
+    for (path, reason) in edit::inline_includes(syntax_tree, dir, limit) {
+        if !args.quiet {
+            let _ = writeln!(io::stderr(), "WARNING: did not inline {}: {}", path, reason);
+        }
+    }
+}
+
+fn print_stats(stats: &edit::Stats, content: &str) {
+    let kinds: Vec<String> = stats
+        .kinds
+        .iter()
+        .map(|(kind, count)| format!("{} {}", count, kind))
+        .collect();
+    let mut stderr = io::stderr();
+    let _ = writeln!(stderr, "Items: {}", kinds.join(", "));
+    let _ = writeln!(stderr, "Lines: {}", content.lines().count());
+    if let Some((path, lines)) = &stats.largest {
+        let _ = writeln!(stderr, "Largest: `{}` ({} lines)", path, lines);
+    }
+}
+
+// Work around rustfmt not being able to parse paths containing $crate. This
+// placeholder should be the same width as $crate to preserve alignments.
+const DOLLAR_CRATE_PLACEHOLDER: &str = "Ξcrate";
+
+// Rustc leaves this behind in place of expressions it could not parse, and syn
+// does not accept it.
+const ERROR: &str = "(/*ERROR*/)";
+const ERROR_PLACEHOLDER: &str = "ΞERROR";
+
+fn insert_placeholders(content: &str) -> String {
+    content
+        .replace("$crate", DOLLAR_CRATE_PLACEHOLDER)
+        .replace(ERROR, ERROR_PLACEHOLDER)
+}
+
+fn unparse(formatter: &Formatter, syntax_tree: &syn::File, compact: bool) -> String {
+    let spaced;
+    let syntax_tree = match formatter {
+        Formatter::Rustfmt(_) | Formatter::Prettyplease if !compact => {
+            let mut file = syntax_tree.clone();
+            insert_blank_lines(&mut file.items);
+            spaced = file;
+            &spaced
+        }
+        _ => syntax_tree,
+    };
+    match formatter {
+        Formatter::Rustfmt(_) => quote!(#syntax_tree).to_string(),
+        Formatter::Ugly => quote!(#syntax_tree).to_string() + "\n",
+        Formatter::Prettyplease => prettyplease::unparse(syntax_tree),
+        Formatter::Json => syn_serde::json::to_string_pretty(syntax_tree) + "\n",
+    }
+}
+
+// Neither the compiler nor the formatters leave blank lines between items, so
+// mark where they go with a macro call that the formatters put on a line of its
+// own, and that finish removes again. Consecutive imports stay together.
+const BLANK_LINE_PLACEHOLDER: &str = "__cargo_expand_blank_line";
+
+fn insert_blank_lines(items: &mut Vec<syn::Item>) {
+    let is_import =
+        |item: &syn::Item| matches!(item, syn::Item::Use(_) | syn::Item::ExternCrate(_));
+    let placeholder = syn::Ident::new(BLANK_LINE_PLACEHOLDER, proc_macro2::Span::call_site());
+    let mut spaced = Vec::with_capacity(items.len() * 2);
+    for mut item in items.drain(..) {
+        if let syn::Item::Mod(syn::ItemMod {
+            content: Some((_, items)),
+            ..
+        }) = &mut item
+        {
+            insert_blank_lines(items);
+        }
+        if let Some(prev) = spaced.last() {
+            if !(is_import(prev) && is_import(&item)) {
+                spaced.push(syn::parse_quote!(#placeholder!();));
+            }
+        }
+        spaced.push(item);
+    }
+    *items = spaced;
+}
+
+// Run rustfmt if that is the formatter, and put back what the placeholders
+// stand for.
+fn finish(
+    args: &Args,
+    formatter: &Formatter,
+    outdir: &Path,
+    mut content: String,
+    color: &Coloring,
+) -> Result<String> {
+    if let Formatter::Rustfmt(rustfmt) = formatter {
+        let user_config = match &args.rustfmt_config {
+            Some(rustfmt_config) => Some(rustfmt_config.clone()),
+            None => project_dir(args).and_then(|dir| fmt::find_rustfmt_config(&dir)),
+        };
+        fmt::write_rustfmt_config(outdir, user_config.as_deref(), args.max_width)?;
+
+        let editions = rustfmt_editions(args);
+        if args.keep_temp {
+            let _ = fs::write(outdir.join("before-rustfmt.rs"), &content);
+        }
+        let fallback = editions.len() > 1;
+        let mut formatted = false;
+        for edition in editions {
+            match run_rustfmt(rustfmt, edition, outdir, &content) {
+                Ok(output) => {
+                    if args.verbose > 1 {
+                        let message = format!("with rustfmt --edition={}", edition);
+                        print_status("Formatted", &message, color);
+                    }
+                    content = output;
+                    formatted = true;
+                    break;
+                }
+                Err(stderr) => {
+                    if args.verbose > 0 {
+                        let _ = writeln!(io::stderr(), "rustfmt --edition={} failed:", edition);
+                        let _ = io::stderr().write_all(stderr.as_bytes());
+                    }
+                }
+            }
+        }
+        if !formatted && !args.quiet {
+            report(
+                args,
+                Level::Warning,
+                Kind::RustfmtFailed,
+                "rustfmt could not format the expansion; showing unformatted output",
+            );
+            if let MessageFormat::Human = message_format(args) {
+                if fallback {
+                    let _ = writeln!(
+                        io::stderr(),
+                        "note: no edition worked, pass --edition to pick one"
+                    );
+                }
+                if args.verbose == 0 {
+                    let _ = writeln!(io::stderr(), "note: pass --verbose to see rustfmt's errors");
+                }
+            }
+        }
+        if args.keep_temp {
+            let _ = fs::write(outdir.join("after-rustfmt.rs"), &content);
+        }
+    }
+
+    content = content.replace(DOLLAR_CRATE_PLACEHOLDER, "$crate");
+    content = content.replace(ERROR_PLACEHOLDER, ERROR);
+    if content.contains(BLANK_LINE_PLACEHOLDER) {
+        let formatted_placeholder = format!("{}!();", BLANK_LINE_PLACEHOLDER);
+        let mut spaced = String::new();
+        for line in content.lines() {
+            if line.trim() != formatted_placeholder {
+                spaced += line;
+            }
+            spaced.push('\n');
+        }
+        // As left by quote if rustfmt failed.
+        content = spaced.replace(&format!("{} ! () ;", BLANK_LINE_PLACEHOLDER), "");
+    }
+    Ok(content)
+}
+
+// Compare the expansion against a previously recorded one, printing the
+// differences if there are any. If there is no snapshot yet, record it.
+fn compare_snapshot(args: &Args, path: &Path, content: &str, color: &Coloring) -> io::Result<bool> {
+    let snapshot = match fs::read_to_string(path) {
+        Ok(snapshot) => snapshot,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            write_output(path, content)?;
+            if !args.quiet {
+                let _ = writeln!(io::stderr(), "Recorded snapshot {}", path.display());
+            }
+            return Ok(true);
+        }
+        Err(err) => return Err(err),
+    };
+
+    if snapshot == content {
+        return Ok(true);
+    }
+
+    let _ = writeln!(
+        io::stderr(),
+        "ERROR: expansion differs from snapshot {}",
+        path.display(),
+    );
+    let snapshot_name = path.display().to_string();
+    diff::print(
+        &snapshot_name,
+        &snapshot,
+        "expansion",
+        content,
+        use_color(color),
+    )?;
+    Ok(false)
+}
+
+// Write the expansion as one file per module under the given directory.
+fn split(
+    args: &Args,
+    formatter: Option<&Formatter>,
+    content: &str,
+    dir: &Path,
+    code: i32,
+    color: &Coloring,
+) -> Result<i32> {
+    let content = insert_placeholders(content);
+    force_fallback();
+    let syntax_tree = match syn::parse_file(&content) {
+        Ok(syntax_tree) => syntax_tree,
+        Err(_) => {
+            let _ = writeln!(
+                io::stderr(),
+                "ERROR: cannot split output that failed to parse"
+            );
+            return Ok(1);
+        }
+    };
+
+    let binary =
+        args.bin.is_some() || args.example.is_some() || args.test.is_some() || args.bench.is_some();
+    let root = if binary { "main.rs" } else { "lib.rs" };
+
+    let outdir = temp_dir(args)?;
+
+    let formatter = formatter.unwrap_or(&Formatter::Ugly);
+    let files = edit::split_modules(syntax_tree, root);
+    for (path, file) in &files {
+        let content = finish(
+            args,
+            formatter,
+            outdir.path(),
+            unparse(formatter, file, args.compact),
+            color,
+        )?;
+        let content = with_line_endings(args, &content);
+        let path = dir.join(path);
+        if let Err(err) = write_output(&path, &content) {
+            let _ = writeln!(
+                io::stderr(),
+                "ERROR: failed to write {}: {}",
+                path.display(),
+                err,
+            );
+            return Ok(1);
+        }
+    }
+
+    if !args.quiet {
+        let _ = writeln!(
+            io::stderr(),
+            "Wrote {} files to {}",
+            files.len(),
+            dir.display(),
+        );
+    }
+    Ok(code)
+}
+
+// Editions to try formatting with, in order.
+fn rustfmt_editions(args: &Args) -> Vec<Edition> {
+    let edition = if args.file.is_some() {
+        Some(args.edition.unwrap_or(Edition::E2021))
+    } else {
+        // Filled in from the package by select_target.
+        args.edition
+    };
+    match edition {
+        Some(edition) => vec![edition],
+        // Probably was the wrong edition if a newer one fails.
+        None => vec![Edition::E2021, Edition::E2018, Edition::E2015],
+    }
+}
+
+// The code to format is passed on stdin.
+fn rustfmt_line(rustfmt: &Path, edition: Edition, config_dir: &Path) -> Line {
+    let mut line = Line::new(rustfmt);
+    line.arg(format!("--edition={}", edition));
+    line.arg("--config-path");
+    line.arg(config_dir);
+    line
+}
+
+// Format code passed through stdin, so that rustfmt does not go looking for
+// the files of any `mod m;` declarations. The error is rustfmt's stderr.
+fn run_rustfmt(
+    rustfmt: &Path,
+    edition: Edition,
+    config_dir: &Path,
+    content: &str,
+) -> std::result::Result<String, String> {
+    let mut child = Command::new(rustfmt)
+        .args(rustfmt_line(rustfmt, edition, config_dir))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| format!("{}\n", err))?;
+    // Rustfmt reads all of its input before writing any output.
+    if let Some(mut stdin) = child.stdin.take() {
+        let _ = stdin.write_all(content.as_bytes());
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|err| format!("{}\n", err))?;
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    if output.status.success() {
+        String::from_utf8(output.stdout).map_err(|_| stderr)
+    } else {
+        Err(stderr)
+    }
+}
+
+// Run the unexpanded source of the target's root file through the same
+// pipeline as the expanded code, as a baseline to compare against.
+fn dump_unexpanded(
+    args: &Args,
+    metadata: &mut LazyMetadata,
+    formatter: Option<&Formatter>,
+    color: &Coloring,
+) -> Result<Expansion> {
+    let source = metadata::root_source(args, metadata)?;
+    let content = fs::read_to_string(&source)?;
+
+    let outdir = temp_dir(args)?;
+
+    timings::record(Phase::Format, || {
+        format(args, formatter, outdir.path(), content, 0, color)
+    })
+}
+
+// Without a target selected, cargo expands the library or the only binary, and
+// fails with a message about rustc arguments when there are several of those.
+// Say which one it is, or what the choices are.
+fn check_default_target(args: &Args, metadata: &mut LazyMetadata, color: &Coloring) -> bool {
+    let selected = args.lib
+        || args.bin.is_some()
+        || args.example.is_some()
+        || args.test.is_some()
+        || args.bench.is_some()
+        || args.targets
+        || args.workspace
+        || args.doc;
+    if selected {
+        return true;
+    }
+
+    // Leave it to cargo to report problems with the package itself.
+    let package = match metadata.package() {
+        Ok(package) => package,
+        Err(_) => return true,
+    };
+
+    let candidates: Vec<String> = package
+        .targets
+        .iter()
+        .filter_map(|target| match target.kind() {
+            TargetKind::Lib => Some("--lib".to_owned()),
+            TargetKind::Bin => Some(format!("--bin {}", target.name)),
+            _ => None,
+        })
+        .collect();
+    if candidates.len() <= 1 {
+        if args.verbose > 0 {
+            let target = package.targets.iter().find(|target| {
+                let kind = target.kind();
+                kind == TargetKind::Lib || kind == TargetKind::Bin
+            });
+            if let Some(target) = target {
+                let message = format!("{} `{}`", target.kind().as_str(), target.name);
+                print_status("Expanding", &message, color);
+            }
+        }
+        return true;
+    }
+
+    let _ = writeln!(
+        io::stderr(),
+        "ERROR: package `{}` has more than one target to expand by default; pick one with:",
+        package.name,
+    );
+    for candidate in &candidates {
+        let _ = writeln!(io::stderr(), "    {}", candidate);
+    }
+    false
+}
+
+// Catch typos in --bin, --example, --test, and --bench before running a build
+// that would fail with a less helpful message.
+fn check_target_names(args: &Args, metadata: &mut LazyMetadata) -> bool {
+    let requested = [
+        (TargetKind::Bin, &args.bin),
+        (TargetKind::Example, &args.example),
+        (TargetKind::Test, &args.test),
+        (TargetKind::Bench, &args.bench),
+    ];
+    if requested.iter().all(|(_, name)| name.is_none()) {
+        return true;
+    }
+
+    // Leave it to cargo to report problems with the package itself.
+    let package = match metadata.package() {
+        Ok(package) => package,
+        Err(_) => return true,
+    };
+
+    for (kind, name) in &requested {
+        let name = match name {
+            Some(name) => name,
+            None => continue,
+        };
+        let names: Vec<&str> = package
+            .targets
+            .iter()
+            .filter(|target| target.kind() == *kind)
+            .map(|target| target.name.as_str())
+            .collect();
+        if names.contains(&name.as_str()) {
+            continue;
+        }
+
+        let _ = writeln!(
+            io::stderr(),
+            "ERROR: no {} target named `{}` in package `{}`",
+            kind.as_str(),
+            name,
+            package.name,
+        );
+        if let Some(suggestion) = metadata::closest(name, &names) {
+            let _ = writeln!(io::stderr(), "Did you mean `{}`?", suggestion);
+        }
+        if names.is_empty() {
+            let _ = writeln!(
+                io::stderr(),
+                "The package has no {} targets.",
+                kind.as_str()
+            );
+        } else {
+            let _ = writeln!(
+                io::stderr(),
+                "Available {} targets: {}",
+                kind.as_str(),
+                names.join(", "),
+            );
+        }
+        return false;
+    }
+
+    true
+}
+
+// Expand each target of the package in turn, concatenating the outputs under a
+// header per target. Failure of one target does not stop the others.
+fn expand_targets(
+    args: &Args,
+    metadata: &mut LazyMetadata,
+    formatter: Option<&Formatter>,
+    color: &Coloring,
+) -> Result<(String, i32)> {
+    let jobs = Jobs::new(args, metadata)?;
+    let package = metadata.package()?;
+
+    let mut targets = Vec::new();
+    let mut runs = Vec::new();
+    for target in &package.targets {
+        let mut args = args.clone();
+        args.tests = false;
+        let kind = target.kind();
+        match kind {
+            TargetKind::Lib => args.lib = true,
+            TargetKind::Bin => args.bin = Some(target.name.clone()),
+            TargetKind::Example => args.example = Some(target.name.clone()),
+            TargetKind::Test => args.test = Some(target.name.clone()),
+            TargetKind::Bench => args.bench = Some(target.name.clone()),
+            TargetKind::CustomBuild => continue,
+        }
+        jobs.set_target_dir(&mut args, runs.len());
+        let outdir = temp_dir(&args)?;
+        runs.push((CargoRun::new(&args, color, outdir.path()), outdir));
+        targets.push((kind, target.name.clone(), args));
+    }
+
+    let mut content = String::new();
+    let mut code = 0;
+    let mut error = None;
+    let mut targets = targets.into_iter();
+    parallel::run(jobs.count, runs, run_cargo, |(output, outdir)| {
+        let (kind, name, args) = targets.next().unwrap();
+        let expansion =
+            output.and_then(|output| format_output(&args, formatter, &outdir, output, color));
+        match expansion {
+            Ok(Expansion::Content(expanded, target_code)) => {
+                if target_code != 0 {
+                    code = target_code;
+                }
+                if !content.is_empty() {
+                    content.push('\n');
+                }
+                content += &format!("// ===== {} {} =====\n\n", kind.as_str(), name);
+                content += &expanded;
+            }
+            Ok(Expansion::Failed(target_code)) => {
+                let _ = writeln!(
+                    io::stderr(),
+                    "ERROR: failed to expand {} `{}`",
+                    kind.as_str(),
+                    name,
+                );
+                code = target_code;
+            }
+            Err(err) => {
+                if error.is_none() {
+                    error = Some(err);
+                }
+            }
+        }
+    });
+
+    match error {
+        Some(err) => Err(err),
+        None => Ok((content, code)),
+    }
+}
+
+// Expands the library of each workspace member, or its binary if it has only
+// one and no library. Without --keep-going, the first member that fails to
+// expand fails the whole run.
+fn expand_workspace(
+    args: &Args,
+    metadata: &mut LazyMetadata,
+    formatter: Option<&Formatter>,
+    color: &Coloring,
+) -> Result<Expansion> {
+    let jobs = Jobs::new(args, metadata)?;
+    let packages = &metadata.get()?.packages;
+
+    for spec in &args.exclude {
+        if !args.quiet && !packages.iter().any(|package| package.name == *spec) {
+            let _ = writeln!(io::stderr(), "WARNING: no such workspace member: {}", spec);
+        }
+    }
+
+    let mut members = Vec::new();
+    let mut runs = Vec::new();
+    for package in packages {
+        if args.exclude.contains(&package.name) {
+            continue;
+        }
+        let mut args = args.clone();
+        args.package = Some(package.name.clone());
+        if args.edition.is_none() {
+            args.edition = package.edition();
+        }
+        let lib = package
+            .targets
+            .iter()
+            .any(|target| target.kind() == TargetKind::Lib);
+        let mut bins = package
+            .targets
+            .iter()
+            .filter(|target| target.kind() == TargetKind::Bin);
+        if lib {
+            args.lib = true;
+        } else if let (Some(bin), None) = (bins.next(), bins.next()) {
+            args.bin = Some(bin.name.clone());
+        } else {
+            if !args.quiet {
+                let _ = writeln!(
+                    io::stderr(),
+                    "WARNING: skipping package `{}`, which has no library and not exactly one binary",
+                    package.name,
+                );
+            }
+            continue;
+        }
+        jobs.set_target_dir(&mut args, runs.len());
+        let outdir = temp_dir(&args)?;
+        runs.push((CargoRun::new(&args, color, outdir.path()), outdir));
+        members.push((package.name.clone(), args));
+    }
+
+    let mut content = String::new();
+    let mut code = 0;
+    let mut failed = None;
+    let mut error = None;
+    let mut members = members.into_iter();
+    parallel::run(jobs.count, runs, run_cargo, |(output, outdir)| {
+        let (name, args) = members.next().unwrap();
+        if failed.is_some() || error.is_some() {
+            return;
+        }
+        let expansion =
+            output.and_then(|output| format_output(&args, formatter, &outdir, output, color));
+        match expansion {
+            Ok(Expansion::Content(expanded, member_code)) => {
+                if member_code != 0 {
+                    code = member_code;
+                }
+                if !content.is_empty() {
+                    content.push('\n');
+                }
+                content += &format!("// ===== package {} =====\n\n", name);
+                content += &expanded;
+            }
+            Ok(Expansion::Failed(member_code)) => {
+                let _ = writeln!(io::stderr(), "ERROR: failed to expand package `{}`", name);
+                code = member_code;
+                if !args.keep_going {
+                    failed = Some(member_code);
+                }
+            }
+            Err(err) => error = Some(err),
+        }
+    });
+
+    match (error, failed) {
+        (Some(err), _) => Err(err),
+        (None, Some(code)) => Ok(Expansion::Failed(code)),
+        (None, None) => Ok(Expansion::Content(content, code)),
+    }
+}
+
+fn expand_doctests(
+    args: &Args,
+    metadata: &mut LazyMetadata,
+    formatter: Option<&Formatter>,
+    color: &Coloring,
+) -> Result<Expansion> {
+    let jobs = Jobs::new(args, metadata)?;
+    let target_directory = match &args.target_dir {
+        Some(dir) => dir.clone(),
+        None => metadata.get()?.target_directory.clone(),
+    };
+    let package = metadata.package()?;
+    let lib = match package
+        .targets
+        .iter()
+        .find(|target| target.kind() == TargetKind::Lib)
+    {
+        Some(lib) => lib,
+        None => {
+            let _ = writeln!(
+                io::stderr(),
+                "ERROR: --doc expands the doctests of a library, but package `{}` has none",
+                package.name,
+            );
+            return Ok(Expansion::Failed(1));
+        }
+    };
+
+    let source = fs::read_to_string(&lib.src_path)?;
+    let doctests = doctest::extract(&source);
+    let package_dir = package
+        .manifest_path
+        .parent()
+        .unwrap_or_else(|| Path::new(""));
+    let location = lib
+        .src_path
+        .strip_prefix(package_dir)
+        .unwrap_or(&lib.src_path);
+    if doctests.is_empty() {
+        let _ = writeln!(
+            io::stderr(),
+            "ERROR: no doctests found in {}",
+            location.display()
+        );
+        return Ok(Expansion::Failed(1));
+    }
+
+    // Builds in the target directory of the package, so that it and its
+    // dependencies are not compiled again.
+    let dir = target_directory.join("expand").join("doctests");
+    let lib_name = lib.name.replace('-', "_");
+    let edition = package.edition().unwrap_or(Edition::E2021);
+    let (manifest_path, names) =
+        doctest::write_package(args, package, &lib_name, edition, &doctests, &dir)?;
+
+    let mut runs = Vec::new();
+    for (i, name) in names.into_iter().enumerate() {
+        let mut args = args.clone();
+        args.manifest_path = Some(manifest_path.clone());
+        args.package = None;
+        args.example = Some(name);
+        args.features.clear();
+        args.all_features = false;
+        args.no_default_features = false;
+        args.target_dir = Some(target_directory.clone());
+        jobs.set_target_dir(&mut args, i);
+        let outdir = temp_dir(&args)?;
+        runs.push((CargoRun::new(&args, color, outdir.path()), outdir));
+    }
+
+    let mut content = String::new();
+    let mut code = 0;
+    let mut error = None;
+    let mut doctests = doctests.iter();
+    parallel::run(jobs.count, runs, run_cargo, |(output, outdir)| {
+        let line = doctests.next().unwrap().line;
+        let expansion =
+            output.and_then(|output| format_output(args, formatter, &outdir, output, color));
+        match expansion {
+            Ok(Expansion::Content(expanded, doctest_code)) => {
+                if doctest_code != 0 {
+                    code = doctest_code;
+                }
+                if !content.is_empty() {
+                    content.push('\n');
+                }
+                content += &format!("// ===== doctest {}:{} =====\n\n", location.display(), line);
+                content += &expanded;
+            }
+            Ok(Expansion::Failed(doctest_code)) => {
+                let _ = writeln!(
+                    io::stderr(),
+                    "ERROR: failed to expand the doctest at {}:{}",
+                    location.display(),
+                    line,
+                );
+                code = doctest_code;
+            }
+            Err(err) => {
+                if error.is_none() {
+                    error = Some(err);
+                }
+            }
+        }
+    });
+
+    match error {
+        Some(err) => Err(err),
+        None => Ok(Expansion::Content(content, code)),
+    }
+}
+
+fn diff_features(
+    args: &Args,
+    metadata: &mut LazyMetadata,
+    formatter: Option<&Formatter>,
+    color: &Coloring,
+) -> Result<i32> {
+    let jobs = Jobs::new(args, metadata)?;
+    let mut runs = Vec::new();
+    for (i, features) in args.diff_features.iter().enumerate() {
+        let mut args = args.clone();
+        args.features = vec![features.clone()];
+        jobs.set_target_dir(&mut args, i);
+        let outdir = temp_dir(&args)?;
+        runs.push((CargoRun::new(&args, color, outdir.path()), outdir));
+    }
+
+    let mut results = Vec::new();
+    parallel::run(jobs.count, runs, run_cargo, |(output, outdir)| {
+        results
+            .push(output.and_then(|output| format_output(args, formatter, &outdir, output, color)));
+    });
+
+    let mut expansions = Vec::new();
+    let mut code = 0;
+    for result in results {
+        match result? {
+            Expansion::Content(content, cargo_code) => {
+                if cargo_code != 0 && !args.keep_going {
+                    code = cargo_code;
+                }
+                expansions.push(content);
+            }
+            Expansion::Failed(code) => return Ok(code),
+        }
+    }
+
+    let old_name = format!("--features {}", args.diff_features[0]);
+    let new_name = format!("--features {}", args.diff_features[1]);
+    if !args.quiet {
+        let _ = writeln!(io::stderr());
+    }
+    let _ = diff::print(
+        &old_name,
+        &expansions[0],
+        &new_name,
+        &expansions[1],
+        use_color(color),
+    );
+
+    Ok(code)
+}
+
+// Beyond this many features there are too many combinations to try.
+const MAX_POWERSET_FEATURES: usize = 10;
+
+// Expand with each combination of features on top of --no-default-features,
+// printing a line per combination as soon as it is done. Only whether the
+// expansion worked is of interest here, so cargo's output is discarded.
+fn feature_powerset(args: &Args, metadata: &mut LazyMetadata) -> Result<i32> {
+    let jobs = Jobs::new(args, metadata)?;
+    let features: Vec<String> = metadata
+        .package()?
+        .features
+        .keys()
+        .filter(|feature| *feature != "default")
+        .cloned()
+        .collect();
+    if features.len() > MAX_POWERSET_FEATURES {
+        let _ = writeln!(
+            io::stderr(),
+            "ERROR: the package has {} features, which is too many to try every combination of (at most {})",
+            features.len(),
+            MAX_POWERSET_FEATURES,
+        );
+        return Ok(1);
+    }
+
+    let combinations: Vec<Vec<String>> = (0..1usize << features.len())
+        .map(|mask| {
+            features
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| mask & 1 << i != 0)
+                .map(|(_, feature)| feature.clone())
+                .collect()
+        })
+        .collect();
+    let labels: Vec<String> = combinations
+        .iter()
+        .map(|combination| {
+            if combination.is_empty() {
+                "(none)".to_owned()
+            } else {
+                combination.join(",")
+            }
+        })
+        .collect();
+    let width = labels.iter().map(String::len).max().unwrap_or(0);
+
+    let mut runs = Vec::new();
+    for (i, combination) in combinations.into_iter().enumerate() {
+        let mut args = args.clone();
+        args.features = combination;
+        args.no_default_features = true;
+        jobs.set_target_dir(&mut args, i);
+
+        let outdir = temp_dir(&args)?;
+        let outfile_path = outdir.path().join("expanded");
+        let mut cmd = Command::new(cargo_binary());
+        apply_args(&mut cmd, &args, &Coloring::Never, &outfile_path);
+        cmd.stdout(Stdio::null()).stderr(Stdio::null());
+        runs.push((cmd, outdir));
+    }
+
+    let mut code = 0;
+    let mut error = None;
+    let mut labels = labels.into_iter();
+    parallel::run(
+        jobs.count,
+        runs,
+        |(mut cmd, _outdir): (Command, OutDir)| cmd.status(),
+        |status| {
+            let label = labels.next().unwrap();
+            match status {
+                Ok(status) => {
+                    let result = if status.success() {
+                        "ok"
+                    } else {
+                        code = 1;
+                        "fails to expand"
+                    };
+                    let _ = writeln!(io::stdout(), "{:width$}  {}", label, result, width = width);
+                }
+                Err(err) => {
+                    if error.is_none() {
+                        error = Some(err);
+                    }
+                }
+            }
+        },
+    );
+
+    match error {
+        Some(err) => Err(err.into()),
+        None => Ok(code),
+    }
+}
+
+// Run on one of the --parallel threads. The output directory is passed along
+// so that it outlives the run.
+fn run_cargo((run, outdir): (CargoRun, OutDir)) -> (Result<CargoOutput>, OutDir) {
+    (run.run(), outdir)
+}
+
+// How many expansions --parallel runs at once. Concurrent cargo invocations
+// would only wait on each other's lock on the target directory, so each
+// thread but the first builds in a target directory of its own.
+struct Jobs {
+    count: usize,
+    target_dir: Option<PathBuf>,
+}
+
+impl Jobs {
+    fn new(args: &Args, metadata: &mut LazyMetadata) -> Result<Self> {
+        let count = args.parallel.unwrap_or(1).max(1);
+        let target_dir = if count > 1 && args.file.is_none() {
+            let dir = match &args.target_dir {
+                Some(dir) => dir.clone(),
+                None => metadata.get()?.target_directory.clone(),
+            };
+            Some(dir.join("expand"))
+        } else {
+            None
+        };
+        Ok(Jobs { count, target_dir })
+    }
+
+    // Point the i'th expansion at the target directory of the thread that
+    // parallel::run will run it on.
+    fn set_target_dir(&self, args: &mut Args, i: usize) {
+        let worker = i % self.count;
+        if worker > 0 {
+            if let Some(dir) = &self.target_dir {
+                args.target_dir = Some(dir.join(format!("job-{}", worker)));
+            }
+        }
+    }
+}
+
+fn print_theme_preview(theme: &str) {
+    const SAMPLE: &str = "\
+#[derive(Debug)]
+struct Point {
+    x: i32,
+}
+
+fn main() {
+    let point = Point { x: 1 };
+    println!(\"{:?}\", point);
+}
+";
+
+    let _ = writeln!(io::stdout(), "\n{}\n", theme);
+    let _ = PrettyPrinter::new()
+        .input_from_bytes(SAMPLE.as_bytes())
+        .language("rust")
+        .tab_width(Some(4))
+        .true_color(false)
+        .header(false)
+        .line_numbers(false)
+        .grid(false)
+        .theme(theme)
+        .print();
+}
+
+// Support cargo-expand built with panic=abort, as otherwise proc-macro2 ends up
+// using a catch_unwind. This switches proc-macro2 over for the whole process,
+// which programs using the library may not want; they can opt out by setting
+// CARGO_EXPAND_NO_FALLBACK.
+fn force_fallback() {
+    if env::var_os("CARGO_EXPAND_NO_FALLBACK").is_none() {
+        proc_macro2::fallback::force();
+    }
+}
+
+fn paging(args: &Args, config: &Config) -> Paging {
+    // A pager would block the next re-expansion in watch mode.
+    if args.no_pager || args.watch || env::var_os("CARGO_EXPAND_NO_PAGER").is_some() {
+        return Paging::Never;
+    }
+
+    match args.pager {
+        Some(paging) => paging,
+        None if config.pager == Some(true) => Paging::Auto,
+        None => Paging::Never,
+    }
+}
+
+fn watch(
+    args: &Args,
+    metadata: &mut LazyMetadata,
+    formatter: Option<&Formatter>,
+    config: &Config,
+    color: &Coloring,
+) -> Result<i32> {
+    // Coalesce the burst of events produced by a single save.
+    const DEBOUNCE: Duration = Duration::from_millis(200);
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = match notify::recommended_watcher(tx) {
+        Ok(watcher) => watcher,
+        Err(err) => {
+            let _ = writeln!(io::stderr(), "ERROR: failed to watch for changes: {}", err);
+            return Ok(1);
+        }
+    };
+
+    let mut paths = Vec::new();
+    if let Some(file) = &args.file {
+        paths.push(file.clone());
+    } else if let Some(dir) = project_dir(args) {
+        paths.push(dir.join("src"));
+        paths.push(
+            args.manifest_path
+                .clone()
+                .unwrap_or_else(|| dir.join("Cargo.toml")),
+        );
+    }
+    for path in &paths {
+        if path.exists() {
+            if let Err(err) = watcher.watch(path, RecursiveMode::Recursive) {
+                if !args.quiet {
+                    let _ = writeln!(
+                        io::stderr(),
+                        "WARNING: failed to watch {}: {}",
+                        path.display(),
+                        err,
+                    );
+                }
+            }
+        }
+    }
+
+    let mut last_success = None;
+    loop {
+        // Clear the screen and move the cursor to the top left.
+        let _ = write!(io::stdout(), "\x1B[2J\x1B[H");
+        let _ = io::stdout().flush();
+
+        // With span locations, proc-macro2 keeps the source text of every
+        // parse for the life of the thread. Nothing from the previous
+        // expansion refers to it anymore.
+        proc_macro2::extra::invalidate_current_thread_spans();
+
+        // The same pipeline as a single expansion, so that every option
+        // applies on each iteration.
+        match produce(args, metadata, formatter, color)? {
+            Expansion::Content(content, code) => {
+                let content = with_line_endings(args, &content);
+                write_content(args, formatter, config, color, content.clone(), code)?;
+                // Files written by --output and --split are left in place
+                // when a later expansion fails.
+                if args.output.is_none() && args.split.is_none() {
+                    last_success = Some(content);
+                }
+            }
+            Expansion::Failed(_) => {
+                if let Some(content) = &last_success {
+                    let _ = writeln!(io::stderr(), "\nShowing the last successful expansion:\n");
+                    print_content(content, args, config, color);
+                }
+            }
+        }
+
+        // Block until something changes, then wait for the events to settle.
+        loop {
+            match rx.recv() {
+                Ok(Ok(event)) if is_modification(&event) => break,
+                Ok(_) => {}
+                Err(_) => return Ok(1),
+            }
+        }
+        while rx.recv_timeout(DEBOUNCE).is_ok() {}
+    }
+}
+
+fn is_modification(event: &notify::Event) -> bool {
+    match event.kind {
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => true,
+        _ => false,
+    }
+}
+
+// Whether to colorize what we write to stdout.
+fn use_color(color: &Coloring) -> bool {
+    match color {
+        Always => true,
+        Never | Html => false,
+        Auto => atty::is(Stdout),
+    }
+}
+
+// Directory of the project being expanded, used as the starting point for
+// discovering its configuration files.
+fn project_dir(args: &Args) -> Option<PathBuf> {
+    let path = match (&args.file, &args.manifest_path) {
+        (Some(path), _) | (None, Some(path)) => path.parent()?.to_owned(),
+        (None, None) => return env::current_dir().ok(),
+    };
+    if path.as_os_str().is_empty() {
+        env::current_dir().ok()
+    } else {
+        Some(path)
+    }
+}
+
+fn write_output(path: &Path, content: &str) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    fs::write(path, content)
+}
+
+fn which_rustfmt(args: &Args) -> Option<PathBuf> {
+    match env::var_os("RUSTFMT") {
+        Some(which) => {
+            if which.is_empty() {
+                None
+            } else {
+                Some(PathBuf::from(which))
+            }
+        }
+        // The rustfmt of the toolchain doing the expansion is the one most
+        // likely to parse its syntax. Without a toolchain of our own, which
+        // includes the re-run one, that is the toolchain rustup has active.
+        None => rustup_which(args.toolchain.as_deref(), "rustfmt")
+            .or_else(|| toolchain_find::find_installed_component("rustfmt")),
+    }
+}
+
+fn rustup_which(toolchain: Option<&str>, binary: &str) -> Option<PathBuf> {
+    let mut cmd = Command::new("rustup");
+    cmd.arg("which");
+    if let Some(toolchain) = toolchain {
+        cmd.arg("--toolchain").arg(toolchain);
+    }
+    let output = cmd.arg(binary).stderr(Stdio::null()).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let path = String::from_utf8(output.stdout).ok()?;
+    Some(PathBuf::from(path.trim_end()))
+}
+
+// Based on https://github.com/rsolomo/cargo-check
+fn apply_args(cmd: &mut Command, args: &Args, color: &Coloring, outfile: &Path) {
+    apply_line(cmd, cargo_line(args, color, outfile), args, color);
+}
+
+fn apply_file_args(cmd: &mut Command, args: &Args, file: &Path, color: &Coloring, outfile: &Path) {
+    apply_line(cmd, rustc_line(args, file, color, outfile), args, color);
+}
+
+fn apply_line(cmd: &mut Command, line: Line, args: &Args, color: &Coloring) {
+    if args.verbose > 0 || args.explain {
+        let mut display = line.clone();
+        display.insert(0, format!("+{}", toolchain(args)));
+        print_command(display, color);
+    }
+
+    cmd.args(line);
+}
+
+// The command line that run_expansion runs: rustc for --file, otherwise cargo.
+fn expansion_line(args: &Args, color: &Coloring, outfile: &Path) -> Line {
+    match &args.file {
+        Some(file) => rustc_line(args, file, color, outfile),
+        None => cargo_line(args, color, outfile),
+    }
+}
+
+fn cargo_line(args: &Args, color: &Coloring, outfile: &Path) -> Line {
+    let mut line = Line::new("cargo");
+
+    line.arg("rustc");
+
+    if let Some(profile) = &args.profile {
+        line.arg(format!("--profile={}", profile));
+    } else if args.tests && args.test.is_none() {
+        line.arg("--profile=test");
+    } else {
+        line.arg("--profile=check");
+    }
+
+    if args.release {
+        line.arg("--release");
+    }
+
+    let features: Vec<&str> = args
+        .features
+        .iter()
+        .flat_map(|features| features.split(|ch: char| ch == ',' || ch.is_whitespace()))
+        .filter(|feature| !feature.is_empty())
+        .collect();
+    if !features.is_empty() {
+        line.arg("--features");
+        line.arg(features.join(","));
+    }
+
+    if args.all_features {
+        line.arg("--all-features");
+    }
+
+    if args.no_default_features {
+        line.arg("--no-default-features");
+    }
+
+    if args.lib {
+        line.arg("--lib");
+    }
+
+    if let Some(bin) = &args.bin {
+        line.arg("--bin");
+        line.arg(bin);
+    }
+
+    if let Some(example) = &args.example {
+        line.arg("--example");
+        line.arg(example);
+    }
+
+    if let Some(test) = &args.test {
+        line.arg("--test");
+        line.arg(test);
+    }
+
+    if let Some(bench) = &args.bench {
+        line.arg("--bench");
+        line.arg(bench);
+    }
+
+    if let Some(target) = &args.target {
+        line.arg("--target");
+        line.arg(target);
+    }
+
+    if let Some(target_dir) = &args.target_dir {
+        line.arg("--target-dir");
+        line.arg(target_dir);
+    }
+
+    if let Some(manifest_path) = &args.manifest_path {
+        line.arg("--manifest-path");
+        line.arg(manifest_path);
+    }
+
+    if let Some(package) = &args.package {
+        line.arg("--package");
+        line.arg(package);
+    }
+
+    if let Some(jobs) = args.jobs {
+        line.arg("--jobs");
+        line.arg(jobs.to_string());
+    }
+
+    if args.verbose > 0 {
+        line.arg("--verbose");
+    }
+
+    if args.quiet {
+        line.arg("--quiet");
+    }
+
+    line.arg("--color");
+    match color {
+        // The html setting applies to the expanded code, not cargo's messages.
+        Coloring::Auto | Coloring::Html => line.arg(if cfg!(not(windows)) && atty::is(Stderr) {
+            "always"
+        } else {
+            "never"
+        }),
+        color => line.arg(color.to_string()),
+    }
+
+    if args.frozen {
+        line.arg("--frozen");
+    }
+
+    if args.locked {
+        line.arg("--locked");
+    }
+
+    if args.offline {
+        line.arg("--offline");
+    }
+
+    for config_override in &args.config_overrides {
+        line.arg("--config");
+        line.arg(config_override);
+    }
+
+    for unstable_flag in &args.unstable_flags {
+        line.arg("-Z");
+        line.arg(unstable_flag);
+    }
+
+    line.arg("--");
+
+    if args.cfg_test {
+        line.arg("--cfg");
+        line.arg("test");
+    }
+
+    // Keep these ahead of -o and -Zunpretty so they can't override them.
+    for rustc_arg in args.rustc_flag.iter().chain(&args.rustc_args) {
+        line.arg(rustc_arg);
+    }
+
+    line.arg("-o");
+    line.arg(outfile);
+    line.arg("-Zunpretty=expanded");
+
+    line
+}
+
+fn rustc_line(args: &Args, file: &Path, color: &Coloring, outfile: &Path) -> Line {
+    let mut line = Line::new("rustc");
+
+    let edition = args.edition.unwrap_or(Edition::E2021);
+    line.arg(format!("--edition={}", edition));
+
+    if let Some(target) = &args.target {
+        line.arg("--target");
+        line.arg(target);
+    }
+
+    line.arg("--color");
+    match color {
+        // The html setting applies to the expanded code, not cargo's messages.
+        Coloring::Auto | Coloring::Html => line.arg(if cfg!(not(windows)) && atty::is(Stderr) {
+            "always"
+        } else {
+            "never"
+        }),
+        color => line.arg(color.to_string()),
+    }
+
+    if args.cfg_test {
+        line.arg("--cfg");
+        line.arg("test");
+    }
+
+    for rustc_arg in args.rustc_flag.iter().chain(&args.rustc_args) {
+        line.arg(rustc_arg);
+    }
+
+    line.arg("-o");
+    line.arg(outfile);
+    line.arg("-Zunpretty=expanded");
+    line.arg(file);
+
+    line
+}
+
+fn print_command(line: Line, color: &Coloring) {
+    print_status("Running", &format!("`{}`", line), color);
+}
+
+// A status line in the style of cargo's.
+fn print_status(status: &str, message: &str, color: &Coloring) {
+    let color_choice = match color {
+        Coloring::Auto | Coloring::Html => ColorChoice::Auto,
+        Coloring::Always => ColorChoice::Always,
+        Coloring::Never => ColorChoice::Never,
+    };
+
+    let mut stream = StandardStream::stderr(color_choice);
+    let _ = stream.set_color(ColorSpec::new().set_bold(true).set_fg(Some(Green)));
+    let _ = write!(stream, "{:>12}", status);
+    let _ = stream.reset();
+    let _ = writeln!(stream, " {}", message);
+}
+
+// Copy the command's stderr to out, except for the lines that are ignored. With
+// a lock timeout, cargo is killed if it stays blocked waiting for a file lock
+// held by another cargo for longer than that.
+fn filter_err(
+    cmd: &mut Command,
+    out: &mut dyn Write,
+    lock_timeout: Option<Duration>,
+    mut ignore: impl FnMut(&str) -> bool,
+) -> io::Result<i32> {
+    let mut child = cmd.stderr(Stdio::piped()).spawn()?;
+    let mut stderr = io::BufReader::new(child.stderr.take().unwrap());
+
+    // Read on another thread so that waiting for the next line can time out.
+    let (sender, receiver) = mpsc::channel();
+    let reader = thread::spawn(move || {
+        let mut line = String::new();
+        while let Ok(n) = stderr.read_line(&mut line) {
+            if n == 0 || sender.send(std::mem::take(&mut line)).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut deadline: Option<Instant> = None;
+    loop {
+        let line = match deadline {
+            None => match receiver.recv() {
+                Ok(line) => line,
+                Err(_) => break,
+            },
+            Some(deadline) => {
+                match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(line) => line,
+                    Err(RecvTimeoutError::Disconnected) => break,
+                    Err(RecvTimeoutError::Timeout) => {
+                        let _ = child.kill();
+                        let _ = child.wait();
+                        let message = format!(
+                            "gave up after {} seconds waiting for a file lock held by another cargo",
+                            lock_timeout.unwrap_or_default().as_secs(),
+                        );
+                        return Err(io::Error::new(io::ErrorKind::TimedOut, message));
+                    }
+                }
+            }
+        };
+        // Anything else that cargo prints means it got the lock.
+        deadline = match lock_timeout {
+            Some(timeout) if line.contains("Blocking waiting for file lock") => {
+                Some(Instant::now() + timeout)
+            }
+            _ => None,
+        };
+        if !ignore(&line) {
+            let _ = write!(out, "{}", line);
+        }
+    }
+    let _ = reader.join();
+    let code = child.wait()?.code().unwrap_or(1);
+    Ok(code)
+}
+
+fn ignore_cargo_err(line: &str) -> bool {
+    if line.trim().is_empty() {
+        return true;
+    }
+
+    let discarded_lines = [
+        "ignoring specified output filename because multiple outputs were \
+         requested",
+        "ignoring specified output filename for 'link' output because multiple \
+         outputs were requested",
+        "ignoring --out-dir flag due to -o flag",
+        "ignoring -C extra-filename flag due to -o flag",
+        "due to multiple output types requested, the explicitly specified \
+         output file name will be adapted for each output type",
+        "warning emitted",
+        "warnings emitted",
+    ];
+    for s in &discarded_lines {
+        if line.contains(s) {
+            return true;
+        }
+    }
+
+    false
+}
+
+// Discards every warning diagnostic, from its `warning:` line through the
+// source snippet and notes that follow it.
+#[derive(Default)]
+struct WarningFilter {
+    in_warning: bool,
+}
+
+impl WarningFilter {
+    fn ignore(&mut self, line: &str) -> bool {
+        let line = strip_ansi(line);
+        if line.starts_with("warning") {
+            self.in_warning = true;
+        } else if line.starts_with("error") || is_cargo_status(&line) {
+            self.in_warning = false;
+        } else if line.trim().is_empty() {
+            // Diagnostics are terminated by a blank line.
+            self.in_warning = false;
+            return true;
+        }
+        self.in_warning
+    }
+}
+
+// Lines like "    Checking foo v0.1.0", right-aligned verbs printed by cargo.
+fn is_cargo_status(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    trimmed.len() < line.len()
+        && trimmed.starts_with(|ch: char| ch.is_ascii_uppercase())
+        && trimmed
+            .split(' ')
+            .next()
+            .map_or(false, |verb| verb.chars().all(char::is_alphabetic))
+}
+
+fn strip_ansi(line: &str) -> String {
+    let mut stripped = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\x1B' {
+            // Skip to the end of the escape sequence, e.g. "\x1B[1;33m".
+            for ch in &mut chars {
+                if ch.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            stripped.push(ch);
+        }
+    }
+    stripped
+}
+
+// Fill in defaults from the cargo config for options not given on the command
+// line.
+fn apply_config(args: &mut Args, config: &Config) {
+    if args.edition.is_none() {
+        if let Some(edition) = &config.edition {
+            match Edition::from_str(edition) {
+                Ok(edition) => args.edition = Some(edition),
+                Err(err) => {
+                    if !args.quiet {
+                        let _ = writeln!(
+                            io::stderr(),
+                            "WARNING: invalid edition in cargo config: {}",
+                            err,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    args.max_width = args.max_width.or(config.max_width);
+
+    let selects_features =
+        !args.features.is_empty() || args.all_features || !args.diff_features.is_empty();
+    if !selects_features {
+        args.features.extend(config.features.clone());
+    }
+}
+
+fn get_color(args: &Args, config: &Config) -> Coloring {
+    match args.color {
+        None | Some(Coloring::Auto) => {}
+        Some(value) => return value,
+    }
+
+    // https://bixense.com/clicolors
+    if env::var_os("CLICOLOR_FORCE").map_or(false, |value| !value.is_empty() && value != "0") {
+        return Coloring::Always;
+    }
+
+    // https://no-color.org
+    if env::var_os("NO_COLOR").map_or(false, |value| !value.is_empty()) {
+        return Coloring::Never;
+    }
+
+    if let Some(value) = args.color {
+        return value;
+    }
+
+    if let Some(value) = config.color.as_ref() {
+        match Coloring::from_str(value.as_str()) {
+            Ok(color) => return color,
+            Err(err) if !args.quiet => {
+                let _ = writeln!(
+                    io::stderr(),
+                    "WARNING: invalid color in cargo config: {}",
+                    err
+                );
+            }
+            Err(_) => {}
+        }
+    }
+
+    Coloring::Auto // default
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::OsStr;
+
+    fn rerun(argv: &[&str]) -> Command {
+        nightly_command("nightly", argv.iter().map(OsString::from))
+    }
+
+    fn forwarded(cmd: &Command) -> Vec<&OsStr> {
+        cmd.get_args().collect()
+    }
+
+    #[test]
+    fn rerun_sets_guard() {
+        let cmd = rerun(&["cargo-expand", "expand", "--lib"]);
+        let guard = cmd
+            .get_envs()
+            .find(|(key, _)| *key == OsStr::new(NO_RUN_NIGHTLY));
+        assert_eq!(
+            guard,
+            Some((OsStr::new(NO_RUN_NIGHTLY), Some(OsStr::new(""))))
+        );
+    }
+
+    #[test]
+    fn rerun_strips_expand_once() {
+        let cmd = rerun(&["cargo-expand", "expand", "expand", "--lib"]);
+        assert_eq!(forwarded(&cmd), ["+nightly", "expand", "expand", "--lib"]);
+
+        let cmd = rerun(&["cargo-expand", "--lib", "expand"]);
+        assert_eq!(forwarded(&cmd), ["+nightly", "expand", "--lib", "expand"]);
+    }
+
+    #[test]
+    fn single_trailing_newline() {
+        assert_eq!(with_trailing_newline("fn main() {}"), "fn main() {}\n");
+        assert_eq!(with_trailing_newline("fn main() {}\n"), "fn main() {}\n");
+        assert_eq!(
+            with_trailing_newline("fn main() {}\n\n\n"),
+            "fn main() {}\n"
+        );
+        assert_eq!(with_trailing_newline(""), "");
+        assert_eq!(
+            with_trailing_newline("fn main() {}\r\n\r\n"),
+            "fn main() {}\r\n"
+        );
+    }
+
+    // A child that writes the given lines to stderr, pausing for the given
+    // number of seconds before each.
+    #[cfg(unix)]
+    fn fake_cargo(lines: &[(u32, &str)]) -> Command {
+        let script: Vec<String> = lines
+            .iter()
+            .map(|(pause, line)| format!("sleep {}; echo '{}' >&2", pause, line))
+            .collect();
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(script.join("; "));
+        cmd
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn lock_timeout_kills_blocked_cargo() {
+        let mut cmd = fake_cargo(&[
+            (0, "    Blocking waiting for file lock on build directory"),
+            (30, "   Compiling late v0.1.0"),
+        ]);
+        let mut out = Vec::new();
+        let start = Instant::now();
+        let err =
+            filter_err(&mut cmd, &mut out, Some(Duration::from_secs(1)), |_| false).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::TimedOut);
+        assert!(start.elapsed() < Duration::from_secs(10));
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Blocking waiting for file lock"));
+        assert!(!out.contains("Compiling"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn lock_timeout_lets_cargo_that_got_the_lock_finish() {
+        let mut cmd = fake_cargo(&[
+            (0, "    Blocking waiting for file lock on build directory"),
+            (0, "   Compiling early v0.1.0"),
+            (2, "    Finished dev"),
+        ]);
+        let mut out = Vec::new();
+        let code = filter_err(&mut cmd, &mut out, Some(Duration::from_secs(1)), |_| false).unwrap();
+        assert_eq!(code, 0);
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Compiling early"));
+        assert!(out.contains("Finished dev"));
+    }
+}
@@ -0,0 +1,145 @@
+// Quick checks of the expanded code for --lint, aimed at macro authors. These
+// are heuristics over the syntax tree, separate from rustc's own diagnostics,
+// and they only see what is left after the options that select items.
+
+use std::collections::BTreeMap;
+
+use proc_macro2::{TokenStream, TokenTree};
+use quote::ToTokens;
+use syn::{File, Item};
+
+use crate::edit;
+
+pub struct Finding {
+    // Path of the item, like `m::Thing`.
+    pub path: String,
+    pub lint: &'static str,
+    pub message: String,
+}
+
+// Names in the std prelude that generated code commonly refers to without a
+// leading `::core::...`, which an item of the same name silently replaces.
+const PRELUDE: &[&str] = &[
+    "Box",
+    "Clone",
+    "Copy",
+    "Default",
+    "Drop",
+    "Eq",
+    "Err",
+    "Fn",
+    "FnMut",
+    "FnOnce",
+    "From",
+    "Into",
+    "Iterator",
+    "None",
+    "Ok",
+    "Option",
+    "Ord",
+    "PartialEq",
+    "PartialOrd",
+    "Result",
+    "Send",
+    "Sized",
+    "Some",
+    "String",
+    "Sync",
+    "ToOwned",
+    "ToString",
+    "Vec",
+];
+
+pub fn check(syntax_tree: &File, dollar_crate: &str) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    check_items(
+        &syntax_tree.items,
+        &mut Vec::new(),
+        dollar_crate,
+        &mut findings,
+    );
+    findings
+}
+
+fn check_items(
+    items: &[Item],
+    module: &mut Vec<String>,
+    dollar_crate: &str,
+    findings: &mut Vec<Finding>,
+) {
+    let path_of = |module: &[String], name: &str| {
+        let mut path = module.join("::");
+        if !path.is_empty() {
+            path += "::";
+        }
+        path + name
+    };
+
+    let mut seen = BTreeMap::new();
+    for item in items {
+        if let Item::Mod(item) = item {
+            if let Some((_, content)) = &item.content {
+                module.push(item.ident.to_string());
+                check_items(content, module, dollar_crate, findings);
+                module.pop();
+            }
+            continue;
+        }
+
+        let name = match item {
+            Item::Impl(_) => format!("<impl {}>", edit::item_name(item).unwrap_or_default()),
+            _ => edit::item_name(item).unwrap_or_else(|| "<item>".to_owned()),
+        };
+        let path = path_of(module, &name);
+
+        // Unless sanitizing removed them, macro_rules definitions are where
+        // `$crate` belongs.
+        let is_macro = matches!(item, Item::Macro(_));
+        if !is_macro && contains_ident(item.to_token_stream(), dollar_crate) {
+            findings.push(Finding {
+                path: path.clone(),
+                lint: "dollar-crate",
+                message: "refers to `$crate`, which is not valid outside of a macro".to_owned(),
+            });
+        }
+
+        let namespace = match item {
+            Item::Struct(_) | Item::Enum(_) | Item::Union(_) | Item::Trait(_) | Item::Type(_) => {
+                "type"
+            }
+            Item::Fn(_) | Item::Static(_) | Item::Const(_) => "value",
+            _ => continue,
+        };
+        // `const _: () = ...` is how derives keep their helpers out of scope.
+        if name == "_" {
+            continue;
+        }
+        if PRELUDE.contains(&name.as_str()) {
+            findings.push(Finding {
+                path: path.clone(),
+                lint: "shadows-prelude",
+                message: format!(
+                    "shadows `{}` from the prelude for macros that refer to it unqualified",
+                    name,
+                ),
+            });
+        }
+        let count = seen.entry((namespace, name)).or_insert(0);
+        *count += 1;
+        if *count == 2 {
+            findings.push(Finding {
+                path,
+                lint: "duplicate-name",
+                message: format!("is defined more than once in the {} namespace", namespace),
+            });
+        }
+    }
+}
+
+fn contains_ident(tokens: TokenStream, ident: &str) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Ident(token) => token == ident,
+        TokenTree::Group(group) => contains_ident(group.stream(), ident),
+        _ => false,
+    })
+}
@@ -0,0 +1,79 @@
+// Maps a source file to the target that compiles it and the module it holds,
+// for --current-file. A file belongs to a target if it is the target's root or
+// can be reached from the root by `mod m;` declarations, in the same way as
+// for --since.
+
+use std::path::{Path, PathBuf};
+
+use crate::error::Result;
+use crate::metadata::{LazyMetadata, TargetKind};
+use crate::since;
+
+pub struct Owner {
+    pub package: String,
+    pub kind: TargetKind,
+    pub target: String,
+    // Path of the module in the file, empty for the root of the target.
+    pub module: Vec<String>,
+}
+
+pub fn owner(
+    metadata: &mut LazyMetadata,
+    package: Option<&str>,
+    file: &Path,
+) -> Result<Option<Owner>> {
+    let file = match file.canonicalize() {
+        Ok(file) => file,
+        Err(_) => return Ok(None),
+    };
+
+    // Of nested packages, the innermost one is the most likely owner.
+    let mut packages: Vec<_> = metadata
+        .get()?
+        .packages
+        .iter()
+        .filter(|candidate| package.map_or(true, |name| candidate.name == name))
+        .filter(|candidate| {
+            candidate
+                .manifest_path
+                .parent()
+                .map_or(false, |dir| file.starts_with(dir))
+        })
+        .collect();
+    packages
+        .sort_by_key(|candidate| std::cmp::Reverse(candidate.manifest_path.components().count()));
+
+    for package in packages {
+        for target in &package.targets {
+            let kind = target.kind();
+            if kind == TargetKind::CustomBuild {
+                continue;
+            }
+            let root = target
+                .src_path
+                .canonicalize()
+                .unwrap_or_else(|_| target.src_path.clone());
+            let module = if root == file {
+                Some(Vec::new())
+            } else {
+                let src_dir = root.parent().unwrap_or_else(|| Path::new("")).to_owned();
+                let mut files: Vec<(PathBuf, Vec<String>)> = Vec::new();
+                since::collect_modules(&root, &src_dir, &mut Vec::new(), &mut files);
+                files
+                    .into_iter()
+                    .find(|(path, _)| *path == file)
+                    .map(|(_, module)| module)
+            };
+            if let Some(module) = module {
+                return Ok(Some(Owner {
+                    package: package.name.clone(),
+                    kind,
+                    target: target.name.clone(),
+                    module,
+                }));
+            }
+        }
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,51 @@
+use std::path::{Path, PathBuf};
+
+// The manifest file that --manifest-path refers to, which may also be given as
+// the directory containing it, as an absolute path without `.` or `..`.
+pub fn resolve(path: &Path) -> Result<PathBuf, String> {
+    let manifest_path = if path.is_dir() {
+        let manifest_path = path.join("Cargo.toml");
+        if !manifest_path.is_file() {
+            return Err(format!("{} does not contain a Cargo.toml", path.display()));
+        }
+        manifest_path
+    } else if path.exists() {
+        path.to_owned()
+    } else {
+        return Err(format!("manifest path {} does not exist", path.display()));
+    };
+    manifest_path
+        .canonicalize()
+        .map_err(|err| format!("failed to resolve {}: {}", manifest_path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn resolve_manifest_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path().canonicalize().unwrap();
+        let manifest_path = dir.join("Cargo.toml");
+
+        assert!(resolve(&dir).is_err());
+
+        fs::write(&manifest_path, "[package]\n").unwrap();
+        assert_eq!(resolve(&manifest_path).unwrap(), manifest_path);
+        assert_eq!(resolve(&dir).unwrap(), manifest_path);
+
+        fs::create_dir(dir.join("sub")).unwrap();
+        let unnormalized = dir.join("sub").join("..").join(".");
+        assert_eq!(resolve(&unnormalized).unwrap(), manifest_path);
+    }
+
+    #[test]
+    fn resolve_missing_manifest_path() {
+        let dir = tempfile::tempdir().unwrap();
+        let missing = dir.path().join("missing").join("Cargo.toml");
+        let err = resolve(&missing).unwrap_err();
+        assert!(err.contains("does not exist"), "{}", err);
+    }
+}
@@ -0,0 +1,65 @@
+// Errors and warnings that tools may want to recognize, written in the format
+// selected by --message-format. As JSON, each is one object on a line of its
+// own on stderr:
+//
+//     {"level":"error","kind":"compile-failed","message":"..."}
+//
+// The kinds are stable; the wording of the messages is not.
+
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::opts::MessageFormat;
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum Level {
+    Error,
+    Warning,
+}
+
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum Kind {
+    // The crate did not compile far enough to be expanded.
+    CompileFailed,
+    // Rustc succeeded but wrote an empty expansion.
+    NoOutput,
+    // The expansion is not valid Rust syntax as far as syn is concerned.
+    ParseFailed,
+    // Rustfmt was asked for but is not installed.
+    RustfmtMissing,
+    // Rustfmt ran but rejected the expansion.
+    RustfmtFailed,
+}
+
+#[derive(Serialize)]
+struct Message<'a> {
+    level: Level,
+    kind: Kind,
+    message: &'a str,
+}
+
+pub fn emit(out: &mut dyn Write, format: MessageFormat, level: Level, kind: Kind, message: &str) {
+    let _ = match format {
+        MessageFormat::Human => {
+            let prefix = match level {
+                Level::Error => "ERROR",
+                Level::Warning => "WARNING",
+            };
+            writeln!(out, "{}: {}", prefix, message)
+        }
+        MessageFormat::Json => {
+            let message = Message {
+                level,
+                kind,
+                message,
+            };
+            match serde_json::to_string(&message) {
+                Ok(json) => writeln!(out, "{}", json),
+                Err(_) => Ok(()),
+            }
+        }
+    };
+}
@@ -0,0 +1,335 @@
+use serde::Deserialize;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::error::{Error, Result};
+use crate::opts::{Args, Edition};
+
+#[derive(Deserialize)]
+pub struct Metadata {
+    pub packages: Vec<Package>,
+    pub target_directory: PathBuf,
+    pub workspace_root: PathBuf,
+    pub workspace_members: Vec<String>,
+}
+
+#[derive(Deserialize)]
+pub struct Package {
+    pub id: String,
+    pub name: String,
+    pub manifest_path: PathBuf,
+    pub targets: Vec<Target>,
+    #[serde(default)]
+    pub features: BTreeMap<String, Vec<String>>,
+    // As resolved by cargo, including `edition.workspace = true`.
+    #[serde(default)]
+    edition: Option<String>,
+}
+
+impl Package {
+    pub fn edition(&self) -> Option<Edition> {
+        self.edition.as_ref()?.parse().ok()
+    }
+
+    // The package's own features that the feature flags turn on, including the
+    // ones enabled by other features. Features of dependencies are left out.
+    pub fn enabled_features(&self, args: &Args) -> BTreeSet<String> {
+        let mut pending: Vec<String> = if args.all_features {
+            self.features.keys().cloned().collect()
+        } else {
+            args.features
+                .iter()
+                .flat_map(|features| features.split(|ch: char| ch == ',' || ch.is_whitespace()))
+                .filter(|feature| !feature.is_empty())
+                .map(str::to_owned)
+                .collect()
+        };
+        if !args.no_default_features {
+            pending.push("default".to_owned());
+        }
+
+        let mut enabled = BTreeSet::new();
+        while let Some(feature) = pending.pop() {
+            let implied = match self.features.get(&feature) {
+                Some(implied) => implied,
+                None => continue,
+            };
+            if !enabled.insert(feature) {
+                continue;
+            }
+            for entry in implied {
+                // `dep:name` only enables the dependency, `name?/feature` only a
+                // feature of it, and `name/feature` both.
+                let name = match entry.find('/') {
+                    Some(slash) => &entry[..slash],
+                    None => entry.as_str(),
+                };
+                if !name.starts_with("dep:") && !name.ends_with('?') {
+                    pending.push(name.to_owned());
+                }
+            }
+        }
+        enabled
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Target {
+    pub name: String,
+    kind: Vec<String>,
+    pub src_path: PathBuf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TargetKind {
+    Lib,
+    Bin,
+    Example,
+    Test,
+    Bench,
+    CustomBuild,
+}
+
+impl Target {
+    pub fn kind(&self) -> TargetKind {
+        let has = |kind: &str| self.kind.iter().any(|k| k == kind);
+        if has("bin") {
+            TargetKind::Bin
+        } else if has("example") {
+            TargetKind::Example
+        } else if has("test") {
+            TargetKind::Test
+        } else if has("bench") {
+            TargetKind::Bench
+        } else if has("custom-build") {
+            TargetKind::CustomBuild
+        } else {
+            // lib, rlib, dylib, proc-macro, etc.
+            TargetKind::Lib
+        }
+    }
+}
+
+impl TargetKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TargetKind::Lib => "lib",
+            TargetKind::Bin => "bin",
+            TargetKind::Example => "example",
+            TargetKind::Test => "test",
+            TargetKind::Bench => "bench",
+            TargetKind::CustomBuild => "build script",
+        }
+    }
+}
+
+// Runs `cargo metadata` on first use only, so that invocations which don't
+// need package information don't pay for it.
+pub struct LazyMetadata {
+    manifest_path: Option<PathBuf>,
+    package: Option<String>,
+    config_overrides: Vec<String>,
+    metadata: Option<Metadata>,
+}
+
+impl LazyMetadata {
+    pub fn new(args: &Args) -> Self {
+        LazyMetadata {
+            manifest_path: args.manifest_path.clone(),
+            package: args.package.clone(),
+            config_overrides: args.config_overrides.clone(),
+            metadata: None,
+        }
+    }
+
+    // Switch to the given package, as if it had been passed as --package.
+    pub fn set_package(&mut self, package: String) {
+        self.package = Some(package);
+    }
+
+    pub fn get(&mut self) -> Result<&Metadata> {
+        if self.metadata.is_none() {
+            self.metadata = Some(run_cargo_metadata(
+                self.manifest_path.as_deref(),
+                &self.config_overrides,
+            )?);
+        }
+        Ok(self.metadata.as_ref().unwrap())
+    }
+
+    // The package selected by --package, or else the one whose manifest is
+    // closest to the --manifest-path or current directory.
+    pub fn package(&mut self) -> Result<&Package> {
+        let spec = self.package.clone();
+        let dir = match &self.manifest_path {
+            Some(manifest_path) => manifest_path.parent().map(Path::to_owned),
+            None => env::current_dir().ok(),
+        };
+        let dir = dir.and_then(|dir| dir.canonicalize().ok());
+
+        let packages = &self.get()?.packages;
+
+        if let Some(spec) = spec {
+            return packages
+                .iter()
+                .find(|package| package.name == spec)
+                .ok_or_else(|| Error::Metadata(format!("package `{}` not found", spec)));
+        }
+
+        let closest = dir.and_then(|dir| {
+            packages
+                .iter()
+                .filter_map(|package| {
+                    let package_dir = package.manifest_path.parent()?;
+                    if dir.starts_with(package_dir) {
+                        Some((package, package_dir.components().count()))
+                    } else {
+                        None
+                    }
+                })
+                .max_by_key(|(_, depth)| *depth)
+                .map(|(package, _)| package)
+        });
+
+        match closest {
+            Some(package) => Ok(package),
+            None if packages.len() == 1 => Ok(&packages[0]),
+            None => Err(Error::Metadata(
+                "could not determine which package to expand; pass --package".to_owned(),
+            )),
+        }
+    }
+}
+
+fn run_cargo_metadata(
+    manifest_path: Option<&Path>,
+    config_overrides: &[String],
+) -> Result<Metadata> {
+    let mut cmd = Command::new(crate::cargo_binary());
+    cmd.arg("metadata");
+    cmd.arg("--format-version=1");
+    cmd.arg("--no-deps");
+    if let Some(manifest_path) = manifest_path {
+        cmd.arg("--manifest-path");
+        cmd.arg(manifest_path);
+    }
+    for config_override in config_overrides {
+        cmd.arg("--config");
+        cmd.arg(config_override);
+    }
+
+    let output = cmd.output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::Metadata(stderr.trim().to_owned()));
+    }
+
+    Ok(serde_json::from_slice(&output.stdout)?)
+}
+
+// The root source file of the target being expanded.
+pub fn root_source(args: &Args, metadata: &mut LazyMetadata) -> Result<PathBuf> {
+    if let Some(file) = &args.file {
+        return Ok(file.clone());
+    }
+
+    let requested = [
+        (TargetKind::Bin, &args.bin),
+        (TargetKind::Example, &args.example),
+        (TargetKind::Test, &args.test),
+        (TargetKind::Bench, &args.bench),
+    ];
+    let package = metadata.package()?;
+    let mut matching = package.targets.iter().filter(|target| {
+        let kind = target.kind();
+        match requested.iter().find(|(_, name)| name.is_some()) {
+            Some((requested_kind, name)) => {
+                kind == *requested_kind && Some(&target.name) == name.as_ref()
+            }
+            None => kind == TargetKind::Lib,
+        }
+    });
+    if let Some(target) = matching.next() {
+        return Ok(target.src_path.clone());
+    }
+
+    // Like cargo, default to the only binary of a package without a library.
+    let mut bins = package
+        .targets
+        .iter()
+        .filter(|target| target.kind() == TargetKind::Bin);
+    match (bins.next(), bins.next()) {
+        (Some(bin), None) if !args.lib => Ok(bin.src_path.clone()),
+        _ => Err(Error::Metadata(
+            "could not determine which target to read; pass --lib or --bin".to_owned(),
+        )),
+    }
+}
+
+// The candidate closest to the given name by edit distance, if any is close
+// enough to plausibly be a typo.
+pub fn closest<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    let max_distance = std::cmp::max(name.chars().count() / 3, 1);
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(name, candidate), *candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b) in b.iter().enumerate() {
+            let substitution = diagonal + if a == *b { 0 } else { 1 };
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use structopt::StructOpt;
+
+    #[test]
+    fn edition_of_workspace_member() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[workspace]\nmembers = [\"member\"]\n\n[workspace.package]\nedition = \"2018\"\n",
+        )
+        .unwrap();
+        let member = dir.path().join("member");
+        fs::create_dir_all(member.join("src")).unwrap();
+        fs::write(
+            member.join("Cargo.toml"),
+            "[package]\nname = \"member\"\nversion = \"0.0.0\"\nedition.workspace = true\n",
+        )
+        .unwrap();
+        fs::write(member.join("src").join("lib.rs"), "").unwrap();
+
+        let manifest_path = dir.path().join("Cargo.toml");
+        let args = Args::from_iter(&[
+            "expand".as_ref(),
+            "--manifest-path".as_ref(),
+            manifest_path.as_os_str(),
+            "--package".as_ref(),
+            "member".as_ref(),
+        ]);
+        let mut metadata = LazyMetadata::new(&args);
+        let package = metadata.package().unwrap();
+        assert_eq!(package.name, "member");
+        assert_eq!(package.edition(), Some(Edition::E2018));
+    }
+}
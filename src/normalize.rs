@@ -0,0 +1,123 @@
+use std::env;
+use std::path::PathBuf;
+
+use crate::metadata::LazyMetadata;
+use crate::opts::Args;
+
+// Rewrites machine-specific absolute paths in the expanded code to
+// placeholders, so that the same expansion in another checkout or on another
+// machine compares equal. Each substitution replaces a path with `$NAME`.
+pub struct Normalizer {
+    substitutions: Vec<(String, String)>,
+}
+
+impl Normalizer {
+    pub fn new(args: &Args, metadata: &mut LazyMetadata) -> Self {
+        let mut paths: Vec<(String, PathBuf)> = Vec::new();
+        if let Ok(metadata) = metadata.get() {
+            paths.push(("WORKSPACE".to_owned(), metadata.workspace_root.clone()));
+        }
+        if let Some(cargo_home) = cargo_home() {
+            paths.push(("CARGO_HOME".to_owned(), cargo_home));
+        }
+        let temp_dir = args.temp_dir.clone().unwrap_or_else(env::temp_dir);
+        paths.push(("TMPDIR".to_owned(), temp_dir));
+
+        // --normalize NAME=PATH replaces the default for NAME or adds a new
+        // one; an empty PATH removes it.
+        for (name, path) in &args.normalize {
+            paths.retain(|(existing, _)| existing != name);
+            if !path.as_os_str().is_empty() {
+                paths.push((name.clone(), path.clone()));
+            }
+        }
+
+        let mut substitutions: Vec<(String, String)> = paths
+            .into_iter()
+            .filter_map(|(name, path)| {
+                let path = path.to_str()?.trim_end_matches('/').to_owned();
+                if path.is_empty() {
+                    None
+                } else {
+                    Some((path, format!("${}", name)))
+                }
+            })
+            .collect();
+
+        // Most specific first, since the workspace is often inside the home
+        // directory or the temporary directory.
+        substitutions.sort_by_key(|(path, _)| std::cmp::Reverse(path.len()));
+
+        Normalizer { substitutions }
+    }
+
+    pub fn apply(&self, content: &str) -> String {
+        let mut content = content.to_owned();
+        for (path, placeholder) in &self.substitutions {
+            content = replace_path(&content, path, placeholder);
+        }
+        content
+    }
+}
+
+// Replace whole occurrences of the path: those that are not the start of a
+// sibling like `/tmpfoo` or `/src/app-utils`, or the end of a longer path like
+// `/var/tmp`. A match is whole when it is followed by a separator, a quote,
+// anything else that cannot be part of a file name, or the end of the content.
+fn replace_path(content: &str, path: &str, placeholder: &str) -> String {
+    let is_name_char = |ch: char| ch.is_alphanumeric() || ch == '_' || ch == '-' || ch == '.';
+    let mut replaced = String::new();
+    let mut copied = 0;
+    for (start, _) in content.match_indices(path) {
+        let end = start + path.len();
+        let before = content[..start].chars().next_back();
+        let after = content[end..].chars().next();
+        if !before.map_or(false, is_name_char) && !after.map_or(false, is_name_char) {
+            replaced += &content[copied..start];
+            replaced += placeholder;
+            copied = end;
+        }
+    }
+    replaced += &content[copied..];
+    replaced
+}
+
+fn cargo_home() -> Option<PathBuf> {
+    if let Some(cargo_home) = env::var_os("CARGO_HOME") {
+        return Some(PathBuf::from(cargo_home));
+    }
+    let home = env::var_os("HOME")?;
+    Some(PathBuf::from(home).join(".cargo"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalizer(substitutions: &[(&str, &str)]) -> Normalizer {
+        Normalizer {
+            substitutions: substitutions
+                .iter()
+                .map(|(path, placeholder)| ((*path).to_owned(), (*placeholder).to_owned()))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn replaces_whole_paths() {
+        let normalizer = normalizer(&[("/src/app", "$WORKSPACE"), ("/tmp", "$TMPDIR")]);
+        assert_eq!(
+            normalizer.apply(r#"include_str!("/src/app/data.txt"); "/tmp""#),
+            r#"include_str!("$WORKSPACE/data.txt"); "$TMPDIR""#,
+        );
+        assert_eq!(normalizer.apply("/tmp"), "$TMPDIR");
+        assert_eq!(normalizer.apply("/tmp/tmp"), "$TMPDIR/tmp");
+    }
+
+    #[test]
+    fn keeps_sibling_prefixes() {
+        let normalizer = normalizer(&[("/src/app", "$WORKSPACE"), ("/tmp", "$TMPDIR")]);
+        let content = r#""/tmpfoo/x.rs" "/src/app-utils/lib.rs" "/var/tmp/y.rs" "/tmp.old""#;
+        assert_eq!(normalizer.apply(content), content);
+    }
+}
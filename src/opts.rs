@@ -2,7 +2,8 @@ use std::fmt::{self, Display};
 use std::path::PathBuf;
 use std::str::FromStr;
 
-use structopt::clap::AppSettings;
+use regex::Regex;
+use structopt::clap::{AppSettings, Shell};
 use structopt::StructOpt;
 use syn_select::Selector;
 
@@ -19,19 +20,40 @@ pub enum Opts {
     Expand(Args),
 }
 
-#[derive(StructOpt, Debug)]
+#[derive(StructOpt, Debug, Clone)]
 #[structopt(rename_all = "kebab-case")]
 pub struct Args {
-    /// Space-separated list of features to activate
-    #[structopt(long, value_name = "FEATURES")]
-    pub features: Option<String>,
+    /// Comma- or space-separated list of features to activate; may be given
+    /// more than once
+    #[structopt(long, value_name = "FEATURES", number_of_values = 1)]
+    pub features: Vec<String>,
+
+    /// Print a diff between the expansions with two sets of features
+    #[structopt(
+        long,
+        value_names = &["A", "B"],
+        number_of_values = 2,
+        conflicts_with = "features"
+    )]
+    pub diff_features: Vec<String>,
+
+    /// Try expanding with every combination of the package's features and
+    /// report which ones fail
+    #[structopt(
+        long,
+        conflicts_with_all = &[
+            "features", "all-features", "no-default-features", "diff-features", "file",
+            "targets", "watch", "check", "cache", "snapshot", "split", "in-context", "output",
+        ]
+    )]
+    pub feature_powerset: bool,
 
     /// Activate all available features
     #[structopt(long)]
     pub all_features: bool,
 
     /// Do not activate the `default` feature
-    #[structopt(long)]
+    #[structopt(long, conflicts_with = "all-features")]
     pub no_default_features: bool,
 
     /// Expand only this package's library
@@ -54,10 +76,53 @@ pub struct Args {
     #[structopt(long)]
     pub tests: bool,
 
+    /// Include code gated on cfg(test) without building with the test profile
+    #[structopt(long, conflicts_with = "tests")]
+    pub cfg_test: bool,
+
     /// Expand only the specified bench target
     #[structopt(long, value_name = "NAME")]
     pub bench: Option<String>,
 
+    /// Print the package's targets that can be expanded
+    #[structopt(long)]
+    pub list_targets: bool,
+
+    /// Expand every target of the package, one after another
+    #[structopt(long, conflicts_with_all = &["lib", "bin", "example", "test", "bench"])]
+    pub targets: bool,
+
+    /// Expand the default target of every workspace member, one after another
+    #[structopt(
+        long,
+        conflicts_with_all = &[
+            "lib", "bin", "example", "test", "bench", "targets", "package", "file",
+            "current-file", "doc", "since", "check", "cache", "explain", "split",
+            "in-context", "dump-unexpanded", "diff-features", "feature-powerset", "watch",
+        ]
+    )]
+    pub workspace: bool,
+
+    /// Skip this workspace member; may be given more than once
+    #[structopt(
+        long,
+        value_name = "SPEC",
+        number_of_values = 1,
+        requires = "workspace"
+    )]
+    pub exclude: Vec<String>,
+
+    /// Expand the doctests in the library's root file
+    #[structopt(
+        long,
+        conflicts_with_all = &[
+            "targets", "lib", "bin", "example", "test", "bench", "tests", "cfg-test",
+            "dump-unexpanded", "in-context", "cache", "check", "explain",
+            "diff-features", "feature-powerset", "watch",
+        ]
+    )]
+    pub doc: bool,
+
     /// Target triple which compiles will be for
     #[structopt(long, value_name = "TARGET")]
     pub target: Option<String>,
@@ -66,10 +131,26 @@ pub struct Args {
     #[structopt(long, value_name = "DIRECTORY", parse(from_os_str))]
     pub target_dir: Option<PathBuf>,
 
-    /// Path to Cargo.toml
+    /// Path to Cargo.toml, or the directory containing it
     #[structopt(long, value_name = "PATH", parse(from_os_str))]
     pub manifest_path: Option<PathBuf>,
 
+    /// Expand a standalone Rust source file without a Cargo project
+    #[structopt(long, value_name = "PATH", parse(from_os_str))]
+    pub file: Option<PathBuf>,
+
+    /// Expand the target that a source file belongs to, showing only the items
+    /// of that file
+    #[structopt(
+        long,
+        value_name = "PATH",
+        parse(from_os_str),
+        conflicts_with_all = &[
+            "file", "lib", "bin", "example", "test", "bench", "targets", "item", "since", "doc",
+        ]
+    )]
+    pub current_file: Option<PathBuf>,
+
     /// Package to expand
     #[structopt(short, long, value_name = "SPEC")]
     pub package: Option<String>,
@@ -78,15 +159,49 @@ pub struct Args {
     #[structopt(long)]
     pub release: bool,
 
+    /// Build with the given profile instead of `check`, or `test` with --tests
+    #[structopt(long, value_name = "PROFILE-NAME", conflicts_with = "release")]
+    pub profile: Option<String>,
+
     /// Number of parallel jobs, defaults to # of CPUs
     #[structopt(short, long, value_name = "N")]
     pub jobs: Option<u64>,
 
-    /// Print command lines as they are executed
+    /// With --targets, --diff-features or --feature-powerset, run up to N
+    /// expansions at the same time
+    #[structopt(long, value_name = "N")]
+    pub parallel: Option<usize>,
+
+    /// Print command lines as they are executed, and with -vv also how the
+    /// output is formatted and printed
+    #[structopt(short, long, parse(from_occurrences))]
+    pub verbose: u64,
+
+    /// Format of errors and warnings: human, json
+    #[structopt(long, value_name = "FMT")]
+    pub message_format: Option<MessageFormat>,
+
+    /// Print the cargo and rustfmt commands that would run, without running
+    /// them
+    #[structopt(
+        long,
+        conflicts_with_all = &["targets", "diff-features", "feature-powerset", "watch"]
+    )]
+    pub explain: bool,
+
+    /// Show all of cargo's output, including lines that are usually hidden
+    #[structopt(long, conflicts_with = "no-warnings")]
+    pub show_warnings: bool,
+
+    /// Hide warnings reported while compiling the crate
     #[structopt(long)]
-    pub verbose: bool,
+    pub no_warnings: bool,
 
-    /// Coloring: auto, always, never
+    /// Print only the expanded code and errors
+    #[structopt(short, long, conflicts_with = "verbose")]
+    pub quiet: bool,
+
+    /// Coloring: auto, always, never, html
     #[structopt(long, value_name = "WHEN")]
     pub color: Option<Coloring>,
 
@@ -98,25 +213,329 @@ pub struct Args {
     #[structopt(long)]
     pub locked: bool,
 
+    /// Run without accessing the network
+    #[structopt(long)]
+    pub offline: bool,
+
+    /// Give up if another cargo holds the file lock for longer than this many
+    /// seconds, instead of waiting for as long as it takes
+    #[structopt(long, value_name = "SECONDS", conflicts_with = "quiet")]
+    pub lock_timeout: Option<u64>,
+
+    /// Override a Cargo configuration value
+    #[structopt(long = "config", value_name = "KEY=VALUE", number_of_values = 1)]
+    pub config_overrides: Vec<String>,
+
     /// Unstable (nightly-only) flags to Cargo
     #[structopt(short = "Z", value_name = "FLAG")]
     pub unstable_flags: Vec<String>,
 
-    /// Do not attempt to run rustfmt
+    /// Rust edition to format with: 2015, 2018, 2021
+    #[structopt(long, value_name = "EDITION")]
+    pub edition: Option<Edition>,
+
+    /// Toolchain to run the expansion with, e.g. nightly-2023-01-01
+    #[structopt(long, value_name = "TOOLCHAIN", env = "CARGO_EXPAND_TOOLCHAIN")]
+    pub toolchain: Option<String>,
+
+    /// Do not attempt to run rustfmt, same as --format=none
     #[structopt(long)]
     pub ugly: bool,
 
-    /// Select syntax highlighting theme
+    /// Print exactly what rustc wrote, without parsing, sanitizing, or
+    /// formatting it
+    #[structopt(
+        long,
+        conflicts_with_all = &[
+            "format", "prettyplease", "json", "no-sanitize", "keep-docs", "compact",
+            "item", "item-regex", "exclude-item", "only", "sorted", "tidy-imports",
+            "strip-attrs", "module", "lint", "stats", "inline-includes", "in-context",
+            "resolve-crate", "split", "line-ending", "cache", "snapshot",
+        ]
+    )]
+    pub raw: bool,
+
+    /// How to format the expansion: rustfmt, prettyplease, none
+    #[structopt(
+        long,
+        value_name = "FORMATTER",
+        conflicts_with_all = &["ugly", "prettyplease", "json"]
+    )]
+    pub format: Option<Formatting>,
+
+    /// Maximum line width for rustfmt to wrap at
+    #[structopt(long, value_name = "N")]
+    pub max_width: Option<usize>,
+
+    /// Path to rustfmt.toml to format with, instead of the project's own
+    #[structopt(long, value_name = "PATH", parse(from_os_str))]
+    pub rustfmt_config: Option<PathBuf>,
+
+    /// Directory to create temporary files in, instead of the system's
+    #[structopt(long, value_name = "PATH", parse(from_os_str))]
+    pub temp_dir: Option<PathBuf>,
+
+    /// Create temporary files in cargo-expand/ under the target directory
+    #[structopt(long, conflicts_with = "temp-dir")]
+    pub use_target_dir: bool,
+
+    /// Keep the temporary directory, with the expansion before and after
+    /// rustfmt, for inspection
+    #[structopt(long)]
+    pub keep_temp: bool,
+
+    /// Do not put blank lines between top-level items
+    #[structopt(long)]
+    pub compact: bool,
+
+    /// Format with prettyplease instead of rustfmt
+    #[structopt(long)]
+    pub prettyplease: bool,
+
+    /// Print the expanded syntax tree as JSON
+    #[structopt(long, conflicts_with_all = &["ugly", "prettyplease"])]
+    pub json: bool,
+
+    /// Replace `$crate` in the output with this crate name or path
+    #[structopt(long, value_name = "NAME")]
+    pub resolve_crate: Option<String>,
+
+    /// Sort top-level items by kind and name for reproducible output
+    #[structopt(long)]
+    pub sorted: bool,
+
+    /// Check the expanded code for common macro mistakes, like `$crate` or
+    /// items shadowing the prelude
+    #[structopt(long)]
+    pub lint: bool,
+
+    /// Print a summary of the expanded items to stderr
+    #[structopt(long)]
+    pub stats: bool,
+
+    /// Replace include_str! and include_bytes! calls left in the output with the
+    /// contents of the file
+    #[structopt(long)]
+    pub inline_includes: bool,
+
+    /// Largest file that --inline-includes inlines, in bytes [default: 16384]
+    #[structopt(long, value_name = "BYTES", requires = "inline-includes")]
+    pub inline_limit: Option<u64>,
+
+    /// Remove duplicate `use` and `extern crate` items
+    #[structopt(long)]
+    pub tidy_imports: bool,
+
+    /// Remove attributes the compiler generates, such as
+    /// #[automatically_derived] and its lint allows; pass a list of attribute
+    /// names, in which `*` matches anything, to remove those instead, or `none`
+    #[structopt(
+        long,
+        value_name = "ATTRS",
+        use_delimiter = true,
+        min_values = 0,
+        require_equals = true
+    )]
+    pub strip_attrs: Option<Vec<String>>,
+
+    /// Do not remove macro_rules definitions and misplaced doc attributes
+    #[structopt(long)]
+    pub no_sanitize: bool,
+
+    /// Keep doc attributes on statements, which are usually misplaced
+    #[structopt(long)]
+    pub keep_docs: bool,
+
+    /// Select syntax highlighting theme, by name or .tmTheme file, or `auto`
+    /// to match the terminal background
     #[structopt(long, value_name = "NAME")]
     pub theme: Option<String>,
 
+    /// Syntax highlighting language, by name or file extension [default: rust]
+    #[structopt(long, value_name = "NAME")]
+    pub language: Option<String>,
+
+    /// Paging: auto, always, never
+    #[structopt(long, value_name = "WHEN")]
+    pub pager: Option<Paging>,
+
+    /// Do not page the output, even if enabled in the config
+    #[structopt(long, conflicts_with = "pager")]
+    pub no_pager: bool,
+
+    /// Command to page the output with, e.g. "less -RFX"
+    #[structopt(long, value_name = "COMMAND", conflicts_with = "no-pager")]
+    pub pager_cmd: Option<String>,
+
+    /// Show line numbers in the highlighted output
+    #[structopt(long)]
+    pub line_numbers: bool,
+
+    /// Draw a grid around the highlighted output
+    #[structopt(long)]
+    pub grid: bool,
+
+    /// Print a header with the file name above the highlighted output
+    #[structopt(long)]
+    pub header: bool,
+
+    /// Width of a tab character in the highlighted output [default: 4]
+    #[structopt(long, value_name = "N")]
+    pub tab_width: Option<usize>,
+
+    /// Wrapping of long lines in the highlighted output: character, never
+    /// [default: never]
+    #[structopt(long, value_name = "MODE")]
+    pub wrap: Option<Wrapping>,
+
+    /// Colors that the terminal can show: truecolor, 256, 16 [default: 256]
+    #[structopt(long, value_name = "PALETTE")]
+    pub color_palette: Option<Palette>,
+
+    /// Line endings of the output: lf, crlf, native [default: native]
+    #[structopt(long, value_name = "STYLE")]
+    pub line_ending: Option<LineEnding>,
+
+    /// Print a completion script for this shell: bash, zsh, fish, powershell,
+    /// elvish
+    #[structopt(long, value_name = "SHELL", hidden = true)]
+    pub completions: Option<Shell>,
+
+    /// Print the names of the package's targets of this kind, for completion
+    #[structopt(long, value_name = "KIND", hidden = true)]
+    pub complete_targets: Option<String>,
+
     /// Print available syntax highlighting theme names
     #[structopt(long)]
     pub themes: bool,
 
+    /// With --themes, show a sample of code rendered in each theme
+    #[structopt(long, requires = "themes")]
+    pub preview: bool,
+
+    /// Only check that the crate expands, without printing the expansion
+    #[structopt(long, conflicts_with_all = &["watch", "diff-features", "targets"])]
+    pub check: bool,
+
+    /// Reuse the previous expansion if the sources and flags are unchanged
+    #[structopt(
+        long,
+        conflicts_with_all = &["file", "targets", "watch", "diff-features"]
+    )]
+    pub cache: bool,
+
+    /// Exit successfully even if the crate failed to compile after expansion
+    #[structopt(long)]
+    pub keep_going: bool,
+
+    /// Report how long each phase of the expansion took
+    #[structopt(long)]
+    pub timings: bool,
+
+    /// Re-expand whenever the source files change
+    #[structopt(long)]
+    pub watch: bool,
+
+    /// Compare the expanded code against this file, recording it there if
+    /// it does not exist yet
+    #[structopt(
+        long,
+        value_name = "PATH",
+        parse(from_os_str),
+        conflicts_with_all = &["output", "split", "watch", "diff-features", "check"]
+    )]
+    pub snapshot: Option<PathBuf>,
+
+    /// Replace PATH in the output of --snapshot and --cache with $NAME; an
+    /// empty PATH disables the default substitution for NAME (WORKSPACE,
+    /// CARGO_HOME or TMPDIR)
+    #[structopt(
+        long,
+        value_name = "NAME=PATH",
+        number_of_values = 1,
+        parse(try_from_str = parse_substitution)
+    )]
+    pub normalize: Vec<(String, PathBuf)>,
+
+    /// Write each module of the expanded code to its own file in this
+    /// directory
+    #[structopt(
+        long,
+        value_name = "DIR",
+        parse(from_os_str),
+        conflicts_with_all = &["output", "json", "targets", "in-context"]
+    )]
+    pub split: Option<PathBuf>,
+
+    /// Write the expanded code to this file instead of stdout
+    #[structopt(short, long, value_name = "PATH", parse(from_os_str))]
+    pub output: Option<PathBuf>,
+
+    /// Print the target's root source file without expanding macros
+    #[structopt(
+        long,
+        conflicts_with_all = &["targets", "diff-features", "watch", "in-context"]
+    )]
+    pub dump_unexpanded: bool,
+
+    /// Print only the contents of this module, e.g. foo::bar; item paths
+    /// are then relative to it
+    #[structopt(long, value_name = "PATH", conflicts_with = "in-context")]
+    pub module: Option<String>,
+
+    /// With --module, also hoist the contents of its submodules
+    #[structopt(long, requires = "module")]
+    pub flatten: bool,
+
+    /// Show the expanded item in place within its unexpanded source file
+    #[structopt(
+        long,
+        requires = "item",
+        conflicts_with_all = &["json", "targets", "item-regex", "diff-features", "watch"]
+    )]
+    pub in_context: bool,
+
     /// Local path to module or other named item to expand, e.g. os::unix::ffi
     #[structopt(value_name = "ITEM", parse(try_from_str = parse_selector))]
-    pub item: Option<Selector>,
+    pub item: Vec<Selector>,
+
+    /// Expand only the modules in files that changed since this git ref
+    #[structopt(
+        long,
+        value_name = "REF",
+        conflicts_with_all = &["item", "in-context", "file", "targets", "doc", "watch"]
+    )]
+    pub since: Option<String>,
+
+    /// Expand only top-level items whose name matches this regex
+    #[structopt(long, value_name = "REGEX", parse(try_from_str = parse_regex))]
+    pub item_regex: Option<Regex>,
+
+    /// Hide this item from the output; may be given more than once
+    #[structopt(
+        long,
+        value_name = "ITEM",
+        number_of_values = 1,
+        parse(try_from_str = parse_selector)
+    )]
+    pub exclude_item: Vec<Selector>,
+
+    /// Show only these kinds of items, e.g. fn,struct,impl
+    #[structopt(long, value_name = "KINDS", use_delimiter = true)]
+    pub only: Vec<ItemKind>,
+
+    /// Extra flag to pass to rustc; may be given more than once
+    #[structopt(
+        long,
+        value_name = "FLAG",
+        number_of_values = 1,
+        allow_hyphen_values = true
+    )]
+    pub rustc_flag: Vec<String>,
+
+    /// Extra flags to pass through to rustc
+    #[structopt(last = true, value_name = "RUSTC_ARGS")]
+    pub rustc_args: Vec<String>,
 }
 
 #[derive(Debug, Clone, Copy)]
@@ -124,6 +543,7 @@ pub enum Coloring {
     Auto,
     Always,
     Never,
+    Html,
 }
 
 impl FromStr for Coloring {
@@ -134,8 +554,9 @@ impl FromStr for Coloring {
             "auto" => Ok(Coloring::Auto),
             "always" => Ok(Coloring::Always),
             "never" => Ok(Coloring::Never),
+            "html" => Ok(Coloring::Html),
             other => Err(format!(
-                "must be auto, always, or never, but found `{}`",
+                "must be auto, always, never, or html, but found `{}`",
                 other,
             )),
         }
@@ -148,6 +569,237 @@ impl Display for Coloring {
             Coloring::Auto => "auto",
             Coloring::Always => "always",
             Coloring::Never => "never",
+            Coloring::Html => "html",
+        };
+        formatter.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Formatting {
+    Rustfmt,
+    Prettyplease,
+    None,
+}
+
+impl FromStr for Formatting {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "rustfmt" => Ok(Formatting::Rustfmt),
+            "prettyplease" => Ok(Formatting::Prettyplease),
+            "none" => Ok(Formatting::None),
+            other => Err(format!(
+                "must be rustfmt, prettyplease, or none, but found `{}`",
+                other,
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Paging {
+    Auto,
+    Always,
+    Never,
+}
+
+impl FromStr for Paging {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "auto" => Ok(Paging::Auto),
+            "always" => Ok(Paging::Always),
+            "never" => Ok(Paging::Never),
+            other => Err(format!(
+                "must be auto, always, or never, but found `{}`",
+                other,
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum MessageFormat {
+    Human,
+    Json,
+}
+
+impl FromStr for MessageFormat {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "human" => Ok(MessageFormat::Human),
+            "json" => Ok(MessageFormat::Json),
+            other => Err(format!("must be human or json, but found `{}`", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Wrapping {
+    Character,
+    Never,
+}
+
+impl FromStr for Wrapping {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "character" => Ok(Wrapping::Character),
+            "never" => Ok(Wrapping::Never),
+            other => Err(format!("must be character or never, but found `{}`", other,)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Palette {
+    TrueColor,
+    Ansi256,
+    Ansi16,
+}
+
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "truecolor" => Ok(Palette::TrueColor),
+            "256" => Ok(Palette::Ansi256),
+            "16" => Ok(Palette::Ansi16),
+            other => Err(format!(
+                "must be truecolor, 256, or 16, but found `{}`",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum LineEnding {
+    Lf,
+    Crlf,
+    Native,
+}
+
+impl FromStr for LineEnding {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "lf" => Ok(LineEnding::Lf),
+            "crlf" => Ok(LineEnding::Crlf),
+            "native" => Ok(LineEnding::Native),
+            other => Err(format!(
+                "must be lf, crlf, or native, but found `{}`",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edition {
+    E2015,
+    E2018,
+    E2021,
+}
+
+impl FromStr for Edition {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "2015" => Ok(Edition::E2015),
+            "2018" => Ok(Edition::E2018),
+            "2021" => Ok(Edition::E2021),
+            other => Err(format!(
+                "must be 2015, 2018, or 2021, but found `{}`",
+                other,
+            )),
+        }
+    }
+}
+
+impl Display for Edition {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Edition::E2015 => "2015",
+            Edition::E2018 => "2018",
+            Edition::E2021 => "2021",
+        };
+        formatter.write_str(name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ItemKind {
+    Const,
+    Enum,
+    ExternCrate,
+    Fn,
+    ForeignMod,
+    Impl,
+    Macro,
+    Mod,
+    Static,
+    Struct,
+    Trait,
+    Type,
+    Union,
+    Use,
+}
+
+impl FromStr for ItemKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "const" => Ok(ItemKind::Const),
+            "enum" => Ok(ItemKind::Enum),
+            "extern-crate" => Ok(ItemKind::ExternCrate),
+            "fn" => Ok(ItemKind::Fn),
+            "foreign-mod" => Ok(ItemKind::ForeignMod),
+            "impl" => Ok(ItemKind::Impl),
+            "macro" => Ok(ItemKind::Macro),
+            "mod" => Ok(ItemKind::Mod),
+            "static" => Ok(ItemKind::Static),
+            "struct" => Ok(ItemKind::Struct),
+            "trait" => Ok(ItemKind::Trait),
+            "type" => Ok(ItemKind::Type),
+            "union" => Ok(ItemKind::Union),
+            "use" => Ok(ItemKind::Use),
+            other => Err(format!(
+                "must be const, enum, extern-crate, fn, foreign-mod, impl, macro, mod, \
+                 static, struct, trait, type, union, or use, but found `{}`",
+                other,
+            )),
+        }
+    }
+}
+
+impl Display for ItemKind {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            ItemKind::Const => "const",
+            ItemKind::Enum => "enum",
+            ItemKind::ExternCrate => "extern-crate",
+            ItemKind::Fn => "fn",
+            ItemKind::ForeignMod => "foreign-mod",
+            ItemKind::Impl => "impl",
+            ItemKind::Macro => "macro",
+            ItemKind::Mod => "mod",
+            ItemKind::Static => "static",
+            ItemKind::Struct => "struct",
+            ItemKind::Trait => "trait",
+            ItemKind::Type => "type",
+            ItemKind::Union => "union",
+            ItemKind::Use => "use",
         };
         formatter.write_str(name)
     }
@@ -160,3 +812,16 @@ fn parse_selector(s: &str) -> Result<Selector, <Selector as FromStr>::Err> {
         s.parse()
     }
 }
+
+// The regex must match the whole name, so that `Foo.*` does not also select
+// `MyFoo`.
+fn parse_regex(s: &str) -> Result<Regex, regex::Error> {
+    Regex::new(&format!("^(?:{})$", s))
+}
+
+fn parse_substitution(s: &str) -> Result<(String, PathBuf), String> {
+    match s.find('=') {
+        Some(eq) if eq > 0 => Ok((s[..eq].to_owned(), PathBuf::from(&s[eq + 1..]))),
+        _ => Err(format!("expected NAME=PATH, found `{}`", s)),
+    }
+}
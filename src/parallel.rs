@@ -0,0 +1,51 @@
+use std::collections::BTreeMap;
+use std::sync::mpsc;
+use std::thread;
+
+// Run the function on every input using `jobs` threads, and pass the results
+// to `done` in the same order as the inputs, each as soon as it and all the
+// ones before it are finished. Input i always runs on thread i % jobs, so that
+// callers can give each thread its own scratch space ahead of time.
+pub fn run<T, R, F>(jobs: usize, inputs: Vec<T>, f: F, mut done: impl FnMut(R))
+where
+    T: Send + 'static,
+    R: Send + 'static,
+    F: Fn(T) -> R + Send + Clone + 'static,
+{
+    let jobs = jobs.max(1);
+    let mut queues: Vec<Vec<(usize, T)>> = (0..jobs).map(|_| Vec::new()).collect();
+    for (i, input) in inputs.into_iter().enumerate() {
+        queues[i % jobs].push((i, input));
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    let workers: Vec<_> = queues
+        .into_iter()
+        .map(|queue| {
+            let f = f.clone();
+            let sender = sender.clone();
+            thread::spawn(move || {
+                for (i, input) in queue {
+                    let _ = sender.send((i, f(input)));
+                }
+            })
+        })
+        .collect();
+    drop(sender);
+
+    let mut finished = BTreeMap::new();
+    let mut next = 0;
+    for (i, result) in receiver {
+        finished.insert(i, result);
+        while let Some(result) = finished.remove(&next) {
+            done(result);
+            next += 1;
+        }
+    }
+
+    for worker in workers {
+        if let Err(panic) = worker.join() {
+            std::panic::resume_unwind(panic);
+        }
+    }
+}
@@ -0,0 +1,174 @@
+// Selects the modules written in files that changed since a git ref, for
+// --since. The mapping is by file: a module is selected when the file that it
+// is declared to live in, by `mod m;`, has changed. Changes to the crate root,
+// or to files that we cannot place in the module tree, such as those pulled in
+// by #[path] or include!, select the whole crate.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use syn::{File, Item};
+use syn_select::Selector;
+
+use crate::error::{Error, Result};
+use crate::metadata::{self, LazyMetadata};
+use crate::opts::Args;
+
+pub enum Changes {
+    Modules(Vec<Selector>),
+    Everything,
+    Nothing,
+}
+
+pub fn changes(args: &Args, metadata: &mut LazyMetadata, git_ref: &str) -> Result<Changes> {
+    let root = metadata::root_source(args, metadata)?;
+    let root = root.canonicalize().unwrap_or(root);
+    let src_dir = root.parent().unwrap_or_else(|| Path::new("")).to_owned();
+
+    let changed = changed_files(&src_dir, git_ref)?;
+    let changed: Vec<PathBuf> = changed
+        .into_iter()
+        .filter(|path| path.extension().map_or(false, |ext| ext == "rs"))
+        .collect();
+
+    let mut files = Vec::new();
+    collect_modules(&root, &src_dir, &mut Vec::new(), &mut files);
+
+    let mut modules = Vec::new();
+    for path in &changed {
+        if *path == root {
+            if !args.quiet {
+                let _ = writeln!(
+                    io::stderr(),
+                    "note: the crate root changed since {}, expanding everything",
+                    git_ref,
+                );
+            }
+            return Ok(Changes::Everything);
+        }
+        match files.iter().find(|(file, _)| file == path) {
+            Some((_, module)) => modules.push(module.clone()),
+            // Files outside of the root's directory belong to other targets,
+            // such as tests and examples.
+            None if path.starts_with(&src_dir) => {
+                if !args.quiet {
+                    let _ = writeln!(
+                        io::stderr(),
+                        "note: {} changed since {} but is not a module file, expanding everything",
+                        path.display(),
+                        git_ref,
+                    );
+                }
+                return Ok(Changes::Everything);
+            }
+            None => {}
+        }
+    }
+
+    // A module that is selected includes its submodules.
+    modules.sort();
+    modules.dedup();
+    let outermost: Vec<&Vec<String>> = modules
+        .iter()
+        .filter(|module| {
+            !modules
+                .iter()
+                .any(|other| other.len() < module.len() && module.starts_with(other))
+        })
+        .collect();
+
+    if outermost.is_empty() {
+        return Ok(Changes::Nothing);
+    }
+    let selectors = outermost
+        .into_iter()
+        .filter_map(|module| module.join("::").parse().ok())
+        .collect();
+    Ok(Changes::Modules(selectors))
+}
+
+// Source files that differ from the ref, including ones that git does not
+// track yet, as absolute paths.
+fn changed_files(dir: &Path, git_ref: &str) -> Result<Vec<PathBuf>> {
+    let toplevel = git(dir, &["rev-parse", "--show-toplevel"])?;
+    let toplevel = PathBuf::from(toplevel.trim_end());
+
+    let diff = git(dir, &["diff", "--name-only", git_ref, "--"])?;
+    let untracked = git(
+        dir,
+        &["ls-files", "--others", "--exclude-standard", "--full-name"],
+    )?;
+
+    Ok(diff
+        .lines()
+        .chain(untracked.lines())
+        .map(|path| {
+            let path = toplevel.join(path);
+            path.canonicalize().unwrap_or(path)
+        })
+        .collect())
+}
+
+fn git(dir: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git").args(args).current_dir(dir).output()?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Error::Git(stderr.trim().to_owned()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// Every file of an out-of-line module reachable from the given file, along
+// with the path of that module.
+pub fn collect_modules(
+    file: &Path,
+    dir: &Path,
+    module: &mut Vec<String>,
+    files: &mut Vec<(PathBuf, Vec<String>)>,
+) {
+    let syntax_tree: File = match fs::read_to_string(file)
+        .ok()
+        .and_then(|source| syn::parse_file(&source).ok())
+    {
+        Some(syntax_tree) => syntax_tree,
+        None => return,
+    };
+    collect_items(&syntax_tree.items, dir, module, files);
+}
+
+fn collect_items(
+    items: &[Item],
+    dir: &Path,
+    module: &mut Vec<String>,
+    files: &mut Vec<(PathBuf, Vec<String>)>,
+) {
+    for item in items {
+        let item = match item {
+            Item::Mod(item) => item,
+            _ => continue,
+        };
+        // Modules moved elsewhere by #[path] are not followed.
+        if item.attrs.iter().any(|attr| attr.path.is_ident("path")) {
+            continue;
+        }
+        let name = item.ident.to_string();
+        let dir = dir.join(&name);
+        module.push(name);
+        match &item.content {
+            Some((_, content)) => collect_items(content, &dir, module, files),
+            None => {
+                let mut file = dir.with_extension("rs");
+                if !file.exists() {
+                    file = dir.join("mod.rs");
+                }
+                if let Ok(file) = file.canonicalize() {
+                    files.push((file.clone(), module.clone()));
+                    collect_modules(&file, &dir, module, files);
+                }
+            }
+        }
+        module.pop();
+    }
+}
@@ -0,0 +1,47 @@
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+// Total nanoseconds spent in each phase, summed over every expansion that ran.
+static CARGO: AtomicU64 = AtomicU64::new(0);
+static FORMAT: AtomicU64 = AtomicU64::new(0);
+static PRINT: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone, Copy)]
+pub enum Phase {
+    Cargo,
+    Format,
+    Print,
+}
+
+impl Phase {
+    fn counter(self) -> &'static AtomicU64 {
+        match self {
+            Phase::Cargo => &CARGO,
+            Phase::Format => &FORMAT,
+            Phase::Print => &PRINT,
+        }
+    }
+}
+
+pub fn record<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    let nanos = start.elapsed().as_nanos() as u64;
+    phase.counter().fetch_add(nanos, Ordering::Relaxed);
+    result
+}
+
+pub fn print() {
+    let phases = [
+        ("cargo", Phase::Cargo),
+        ("format", Phase::Format),
+        ("print", Phase::Print),
+    ];
+    let mut stderr = io::stderr();
+    let _ = writeln!(stderr, "Timings:");
+    for (name, phase) in &phases {
+        let elapsed = Duration::from_nanos(phase.counter().load(Ordering::Relaxed));
+        let _ = writeln!(stderr, "    {:<8}{:>8.2}s", name, elapsed.as_secs_f64());
+    }
+}
@@ -0,0 +1,28 @@
+// Themes loaded from a .tmTheme file given to --theme. Bat only offers a way
+// to add themes by loading them from a directory, so the file is copied into
+// a temporary one laid out the way bat expects.
+
+use std::fs;
+use std::path::Path;
+
+use bat::assets::HighlightingAssets;
+use syntect::highlighting::ThemeSet;
+
+// Bat's assets with the theme added, and the name to select it by.
+pub fn load(path: &Path) -> Result<(HighlightingAssets, String), String> {
+    // Bat prints to stdout about themes that fail to load, so check first.
+    ThemeSet::get_theme(path).map_err(|err| err.to_string())?;
+
+    let name = match path.file_stem() {
+        Some(stem) => stem.to_string_lossy().into_owned(),
+        None => return Err("not a file".to_owned()),
+    };
+    let dir = tempfile::tempdir().map_err(|err| err.to_string())?;
+    let themes = dir.path().join("themes");
+    fs::create_dir(&themes).map_err(|err| err.to_string())?;
+    fs::create_dir(dir.path().join("syntaxes")).map_err(|err| err.to_string())?;
+    fs::copy(path, themes.join(format!("{}.tmTheme", name))).map_err(|err| err.to_string())?;
+
+    let assets = HighlightingAssets::from_files(dir.path(), true).map_err(|err| err.to_string())?;
+    Ok((assets, name))
+}